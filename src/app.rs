@@ -1,35 +1,57 @@
 use crate::{
+    cancel::TxCancel,
     payload::{EsbHeader, PayloadR, PayloadW},
     peripherals::{Interrupt, NVIC},
-    Error,
+    report::{ProbeResult, TxReport, TxReports},
+    stats::{EsbStats, LinkStats},
+    Error, PayloadLength, Protocol,
 };
 use bbqueue::{
-    framed::{FrameConsumer, FrameProducer},
+    consts::U0,
+    framed::{FrameConsumer, FrameGrantW, FrameProducer},
     ArrayLength, Error as BbqError,
 };
-use core::default::Default;
+use core::{
+    default::Default,
+    sync::atomic::{AtomicU16, Ordering},
+};
 
 /// This is the primary Application-side interface.
 ///
 /// It is intended to be used outside of the `RADIO` interrupt,
 /// and allows for sending or receiving frames from the ESB Radio
 /// hardware.
-pub struct EsbApp<OutgoingLen, IncomingLen>
+pub struct EsbApp<OutgoingLen, IncomingLen, PriorityLen = U0>
 where
     OutgoingLen: ArrayLength<u8>,
     IncomingLen: ArrayLength<u8>,
+    PriorityLen: ArrayLength<u8>,
 {
     // TODO(AJM): Make a constructor for this so we don't
     // need to make these fields pub(crate)
     pub(crate) prod_to_radio: FrameProducer<'static, OutgoingLen>,
+    pub(crate) prod_priority_to_radio: FrameProducer<'static, PriorityLen>,
     pub(crate) cons_from_radio: FrameConsumer<'static, IncomingLen>,
     pub(crate) maximum_payload: u8,
+    pub(crate) pipe_maximum_payload: [u8; 8],
+    pub(crate) payload_length: PayloadLength,
+    pub(crate) protocol: Protocol,
+    pub(crate) channel_request: &'static AtomicU16,
+    pub(crate) channel_request_seq: u8,
+    pub(crate) tx_reports: &'static TxReports,
+    pub(crate) tx_cancel: &'static TxCancel,
+    pub(crate) probe_seq: u8,
+    pub(crate) stats: &'static EsbStats,
+    pub(crate) stats_baseline: LinkStats,
+    pub(crate) auto_pid: bool,
+    pub(crate) next_pid: [u8; 8],
 }
 
-impl<OutgoingLen, IncomingLen> EsbApp<OutgoingLen, IncomingLen>
+impl<OutgoingLen, IncomingLen, PriorityLen> EsbApp<OutgoingLen, IncomingLen, PriorityLen>
 where
     OutgoingLen: ArrayLength<u8>,
     IncomingLen: ArrayLength<u8>,
+    PriorityLen: ArrayLength<u8>,
 {
     /// Obtain a grant for an outgoing packet to be sent over the Radio
     ///
@@ -45,22 +67,176 @@ where
     /// `drop` the old grant, and create a new one.
     ///
     /// Only one grant may be active at a time.
+    ///
+    /// The length of the header must not exceed the maximum payload size of its pipe, see
+    /// [`PipeConfig`](../struct.PipeConfig.html).
+    ///
+    /// When using [`PayloadLength::Static`](../enum.PayloadLength.html), the length of the header
+    /// must be equal to the maximum payload size of its pipe, otherwise
+    /// `Error::InvalidParameters` is returned. The whole payload is always committed in this mode.
+    ///
+    /// When using [`Protocol::ShockBurst`](../enum.Protocol.html), the header must not request an
+    /// acknowledgement and its pid must be zero, as these fields are not sent in this mode.
+    ///
+    /// When the automatic pid is enabled, see [`set_auto_pid`](#method.set_auto_pid), the pid of
+    /// the header is replaced.
     pub fn grant_packet(&mut self, header: EsbHeader) -> Result<PayloadW<OutgoingLen>, Error> {
-        // Check we have not exceeded the configured packet max
-        if header.length > self.maximum_payload {
+        let header = self.check_header(header, self.auto_pid)?;
+        let grant = grant_from(&mut self.prod_to_radio, header)?;
+        self.advance_pid(header, self.auto_pid);
+        let fixed_length = self.payload_length != PayloadLength::Dynamic;
+        Ok(PayloadW::new_from_app(grant, header, fixed_length))
+    }
+
+    /// Obtain a grant for an outgoing packet in the priority queue
+    ///
+    /// Packets of the priority queue are sent before the ones of the normal queue, in PTX mode as
+    /// soon as the packet being sent is done, and in PRX mode as the payload of the next
+    /// acknowledgement. The same rules of [`grant_packet`](#method.grant_packet) apply.
+    ///
+    /// An error will be returned if the priority queue has no room, its size is zero unless
+    /// given to [`EsbBuffer`](../buffer/struct.EsbBuffer.html).
+    pub fn grant_priority_packet(
+        &mut self,
+        header: EsbHeader,
+    ) -> Result<PayloadW<PriorityLen>, Error> {
+        let header = self.check_header(header, self.auto_pid)?;
+        let grant = grant_from(&mut self.prod_priority_to_radio, header)?;
+        self.advance_pid(header, self.auto_pid);
+        let fixed_length = self.payload_length != PayloadLength::Dynamic;
+        Ok(PayloadW::new_from_app(grant, header, fixed_length))
+    }
+
+    // Checks an outgoing header against the configuration and assigns its cancellation epoch, and
+    // its automatic pid if `auto_pid` is set
+    fn check_header(&self, mut header: EsbHeader, auto_pid: bool) -> Result<EsbHeader, Error> {
+        // Check we have not exceeded the configured packet max of the pipe
+        if header.length > self.pipe_maximum_payload[usize::from(header.pipe())] {
             return Err(Error::MaximumPacketExceeded);
         }
+        // Each pipe has its own static length, like the `RX_PW_Px` registers of the nRF24L01+
+        let fixed_length = self.payload_length != PayloadLength::Dynamic;
+        if fixed_length && header.length != self.pipe_maximum_payload[usize::from(header.pipe())] {
+            return Err(Error::InvalidParameters);
+        }
+        // The pid is not sent in the ShockBurst mode
+        if auto_pid && self.protocol == Protocol::EnhancedShockBurst {
+            header.set_pid(self.next_pid[usize::from(header.pipe())]);
+        }
+        if self.protocol == Protocol::ShockBurst && (!header.no_ack() || header.pid() != 0) {
+            return Err(Error::InvalidParameters);
+        }
 
-        let grant_result = self
-            .prod_to_radio
-            .grant(header.payload_len() + EsbHeader::header_size());
+        header.set_epoch(self.tx_cancel.epoch(header.pipe()));
+        Ok(header)
+    }
 
-        let grant = grant_result.map_err(|err| match err {
-            BbqError::GrantInProgress => Error::GrantInProgress,
-            BbqError::InsufficientSize => Error::OutgoingQueueFull,
-            _ => Error::InternalError,
-        })?;
-        Ok(PayloadW::new_from_app(grant, header))
+    // Consumes the automatic pid of a granted header
+    fn advance_pid(&mut self, header: EsbHeader, auto_pid: bool) {
+        if auto_pid && self.protocol == Protocol::EnhancedShockBurst {
+            let next_pid = &mut self.next_pid[usize::from(header.pipe())];
+            *next_pid = (*next_pid + 1) & 0b11;
+        }
+    }
+
+    /// Enables or disables the automatic pid, disabled by default.
+    ///
+    /// When enabled, [`grant_packet`](#method.grant_packet) assigns the pid of each packet from a
+    /// counter kept per pipe, so consecutive packets of a pipe are never taken as retransmissions
+    /// by the PRX. A pid is consumed even if the grant is dropped without being committed. When
+    /// disabled, the pid of the header is used as is.
+    pub fn set_auto_pid(&mut self, enabled: bool) {
+        self.auto_pid = enabled;
+    }
+
+    /// Is the automatic pid enabled?
+    pub fn auto_pid(&self) -> bool {
+        self.auto_pid
+    }
+
+    /// Sends a link probe to `pipe`, a packet without payload that requests an acknowledgement,
+    /// and starts the transmission of the queue.
+    ///
+    /// The probe goes through the outgoing queue and follows the same retransmission rules as the
+    /// other packets. Its result, including the round-trip time, can be read with
+    /// [`probe_result`](#method.probe_result) once the PTX is done with it. Sending a new probe
+    /// replaces the result of the previous one. The probe is not available in the static payload
+    /// length mode or in the ShockBurst mode.
+    ///
+    /// The pid of the probe is always taken from the counter of the automatic pid, even when it
+    /// is disabled, so consecutive probes are not taken as retransmissions by the PRX. The
+    /// round-trip time only covers the last transmission of the probe, the number of attempts is
+    /// reported separately.
+    pub fn probe(&mut self, pipe: u8) -> Result<(), Error> {
+        let mut header = EsbHeader::build().pipe(pipe).no_ack(false).check()?;
+        // Zero is the sequence number before the first probe
+        let seq = match self.probe_seq.wrapping_add(1) {
+            0 => 1,
+            seq => seq,
+        };
+        header.set_probe(seq);
+        let header = self.check_header(header, true)?;
+        let grant = grant_from(&mut self.prod_to_radio, header)?;
+        self.advance_pid(header, true);
+        PayloadW::new_from_app(grant, header, false).commit(0);
+        self.probe_seq = seq;
+        self.start_tx();
+        Ok(())
+    }
+
+    /// Result of the last probe sent with [`probe`](#method.probe), `None` if it is still in
+    /// progress or if no probe was sent.
+    pub fn probe_result(&self) -> Option<ProbeResult> {
+        if self.probe_seq == 0 {
+            return None;
+        }
+        self.tx_reports.probe(self.probe_seq)
+    }
+
+    /// Link statistics since the buffer was split or since the last call to
+    /// [`reset_stats`](#method.reset_stats), see the [stats](../stats/index.html) module
+    pub fn stats(&self) -> LinkStats {
+        self.stats.snapshot().since(&self.stats_baseline)
+    }
+
+    /// Restarts the link statistics from zero
+    pub fn reset_stats(&mut self) {
+        self.stats_baseline = self.stats.snapshot();
+    }
+
+    /// Discards the packets of the pipes in the `pipes` bit mask, the LSb is pipe zero, that are
+    /// waiting in the outgoing queues, or that were granted and not yet committed.
+    ///
+    /// The packets are discarded by the radio interrupt when they reach the head of the queue,
+    /// see the [cancel](../cancel/index.html) module, tagged packets are reported as
+    /// [`TxOutcome::Cancelled`](../report/enum.TxOutcome.html). Packets granted after this call
+    /// are not affected. In PTX mode, this pends the radio interrupt, which discards the packets
+    /// and starts the transmission of the remaining ones, like [`start_tx`](#method.start_tx).
+    /// In PRX mode, the receiver isn't started.
+    pub fn cancel(&mut self, pipes: u8) {
+        self.tx_cancel.cancel(pipes);
+        if self.tx_cancel.ptx() {
+            NVIC::pend(Interrupt::RADIO);
+        }
+    }
+
+    /// Discards all the packets waiting in the outgoing queues, see [`cancel`](#method.cancel)
+    ///
+    /// In PTX mode, the flush is complete once the PTX is idle again, i.e. when
+    /// [`EsbIrq::radio_interrupt`](../irq/struct.EsbIrq.html#method.radio_interrupt) returns
+    /// `StatePTX::IdleTx`, the outgoing queues are empty then and
+    /// [`discarded_packets`](#method.discarded_packets) counts all the flushed packets.
+    pub fn flush(&mut self) {
+        self.cancel(0xFF);
+    }
+
+    /// Number of packets discarded by [`cancel`](#method.cancel) and [`flush`](#method.flush)
+    /// since the buffer was split, the count wraps around.
+    ///
+    /// The count of a cancellation is complete once the discarded packets reached the head of
+    /// the queue, e.g. when the PTX is idle, see the [cancel](../cancel/index.html) module.
+    pub fn discarded_packets(&self) -> u32 {
+        self.tx_cancel.discarded()
     }
 
     /// Starts the radio sending all packets in the queue.
@@ -76,6 +252,25 @@ where
         NVIC::pend(Interrupt::RADIO)
     }
 
+    /// Requests a change of the RF channel, it must be between 0 and 100.
+    ///
+    /// The change is applied by the radio interrupt at the next safe point, as described by the
+    /// `set_channel` methods of [`EsbIrq`](../irq/struct.EsbIrq.html). In PTX mode, this also
+    /// starts the transmission of the packets in the queue, like
+    /// [`start_tx`](#method.start_tx).
+    pub fn request_channel(&mut self, channel: u8) -> Result<(), Error> {
+        if channel > 100 {
+            return Err(Error::InvalidParameters);
+        }
+        self.channel_request_seq = self.channel_request_seq.wrapping_add(1);
+        self.channel_request.store(
+            pack_channel_request(self.channel_request_seq, channel),
+            Ordering::Release,
+        );
+        NVIC::pend(Interrupt::RADIO);
+        Ok(())
+    }
+
     /// Is there a received message that is ready to be read?
     ///
     /// Returns `true` if a call to `read_packet` would return `Some`.
@@ -92,6 +287,14 @@ where
         self.cons_from_radio.read().map(PayloadR::new)
     }
 
+    /// Attempt to read the report of a tagged packet, in the order the driver finished them.
+    ///
+    /// Returns `None` if there is no report waiting. See the [`report`](../report/index.html)
+    /// module.
+    pub fn read_tx_report(&mut self) -> Option<TxReport> {
+        self.tx_reports.pop()
+    }
+
     /// Gets the maximum payload size (in bytes) that the driver was configured to use.
     #[inline]
     pub fn maximum_payload_size(&self) -> usize {
@@ -99,6 +302,55 @@ where
     }
 }
 
+// Grants room for an outgoing packet and its header in a queue
+fn grant_from<N: ArrayLength<u8>>(
+    producer: &mut FrameProducer<'static, N>,
+    header: EsbHeader,
+) -> Result<FrameGrantW<'static, N>, Error> {
+    producer
+        .grant(header.payload_len() + header.queue_size())
+        .map_err(|err| match err {
+            BbqError::GrantInProgress => Error::GrantInProgress,
+            BbqError::InsufficientSize => Error::OutgoingQueueFull,
+            _ => Error::InternalError,
+        })
+}
+
+// Channel requests hold a sequence number in the high byte and the channel in the low byte
+pub(crate) fn pack_channel_request(seq: u8, channel: u8) -> u16 {
+    (u16::from(seq) << 8) | u16::from(channel)
+}
+
+// Returns the sequence number and the channel of a request
+pub(crate) fn unpack_channel_request(request: u16) -> (u8, u8) {
+    ((request >> 8) as u8, request as u8)
+}
+
+/// Total on-air address width, base address plus the 1-byte prefix
+///
+/// This is equivalent to the `SETUP_AW` register of the nRF24L01+.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AddressWidth {
+    /// 2-byte base address plus 1-byte prefix
+    ThreeBytes,
+    /// 3-byte base address plus 1-byte prefix
+    FourBytes,
+    /// 4-byte base address plus 1-byte prefix
+    FiveBytes,
+}
+
+impl AddressWidth {
+    /// Length of the base address in bytes
+    pub(crate) fn base_len(self) -> u8 {
+        match self {
+            AddressWidth::ThreeBytes => 2,
+            AddressWidth::FourBytes => 3,
+            AddressWidth::FiveBytes => 4,
+        }
+    }
+}
+
 /// Addresses used for communication.
 ///
 /// ESB uses up to eight pipes to address communication, each pipe has an unique address which is
@@ -107,14 +359,28 @@ where
 ///
 /// Default values:
 ///
-/// | Field      | Default Value            |
-/// | :---       | :---                     |
-/// | base0      | [0xE7, 0xE7, 0xE7, 0xE7] |
-/// | base1      | [0xC2, 0xC2, 0xC2, 0xC2] |
-/// | prefixes0  | [0xE7, 0xC2, 0xC3, 0xC4] |
-/// | prefixes1  | [0xC5, 0xC6, 0xC7, 0xC8] |
-/// | rf_channel | 2                        |
+/// | Field         | Default Value            |
+/// | :---          | :---                     |
+/// | base0         | [0xE7, 0xE7, 0xE7, 0xE7] |
+/// | base1         | [0xC2, 0xC2, 0xC2, 0xC2] |
+/// | prefixes0     | [0xE7, 0xC2, 0xC3, 0xC4] |
+/// | prefixes1     | [0xC5, 0xC6, 0xC7, 0xC8] |
+/// | rf_channel    | 2                        |
+/// | address_width | 5 bytes                  |
 ///
+/// Addresses can be stored or transferred by using their versioned binary encoding, see
+/// [to_bytes](#method.to_bytes) and [from_bytes](#method.from_bytes). With the `serde` feature,
+/// they also implement `Serialize` and `Deserialize`, deserialization goes through the same
+/// validation as the constructors.
+#[derive(Copy, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(
+        into = "crate::encoding::AddressesRepr",
+        try_from = "crate::encoding::AddressesRepr"
+    )
+)]
 pub struct Addresses {
     /// Base address for pipe 0
     pub(crate) base0: [u8; 4],
@@ -126,10 +392,13 @@ pub struct Addresses {
     pub(crate) prefixes1: [u8; 4],
     /// Channel to be used by the radio hardware (must be between 0 and 100)
     pub(crate) rf_channel: u8,
+    /// Total width of the addresses, only the first `width - 1` bytes of the base addresses are
+    /// used, the remaining bytes are always zero
+    pub(crate) address_width: AddressWidth,
 }
 
 impl Addresses {
-    /// Creates a new instance of `Addresses`
+    /// Creates a new instance of `Addresses` using 5-byte addresses
     ///
     /// * `base0` - Base address for pipe 0.
     /// * `base1` - Base address for pipe 1-7.
@@ -137,30 +406,353 @@ impl Addresses {
     /// * `prefixes1` - Prefixes for pipes 4-7, in order.
     /// * `rf_channel` - Channel to be used by the radio hardware (must be between 0 and 100).
     ///
-    /// # Panics
+    /// # Errors
     ///
-    /// This function will panic if `rf_channel` is bigger than 100.
+    /// This function will return an error if `rf_channel` is bigger than 100 or if two pipes end
+    /// up with the same address.
     pub fn new(
         base0: [u8; 4],
         base1: [u8; 4],
         prefixes0: [u8; 4],
         prefixes1: [u8; 4],
         rf_channel: u8,
+    ) -> Result<Self, Error> {
+        Self::new_with_width(
+            AddressWidth::FiveBytes,
+            &base0,
+            &base1,
+            prefixes0,
+            prefixes1,
+            rf_channel,
+        )
+    }
+
+    /// Creates a new instance of `Addresses` with the given address width
+    ///
+    /// * `address_width` - Total width of the addresses, including the prefix.
+    /// * `base0` - Base address for pipe 0, must be `address_width - 1` bytes long.
+    /// * `base1` - Base address for pipe 1-7, must be `address_width - 1` bytes long.
+    /// * `prefixes0` - Prefixes for pipes 0-3, in order.
+    /// * `prefixes1` - Prefixes for pipes 4-7, in order.
+    /// * `rf_channel` - Channel to be used by the radio hardware (must be between 0 and 100).
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if `rf_channel` is bigger than 100, if the length of
+    /// the base addresses doesn't match `address_width` or if two pipes end up with the same
+    /// address, i.e. pipes 1-7 must have different prefixes and pipe 0 must differ from all of
+    /// them.
+    ///
+    /// Weak addresses are accepted, they can be checked with
+    /// [weak_pipes](#method.weak_pipes), or rejected with [new_strict](#method.new_strict).
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use esb::{Addresses, AddressWidth};
+    ///
+    /// let addresses = Addresses::new_with_width(
+    ///     AddressWidth::ThreeBytes,
+    ///     &[0xE7, 0xE7],
+    ///     &[0xC2, 0xC2],
+    ///     [0xE7, 0xC2, 0xC3, 0xC4],
+    ///     [0xC5, 0xC6, 0xC7, 0xC8],
+    ///     2,
+    /// );
+    /// assert!(addresses.is_ok());
+    ///
+    /// let addresses = Addresses::new_with_width(
+    ///     AddressWidth::ThreeBytes,
+    ///     &[0xE7, 0xE7, 0xE7],
+    ///     &[0xC2, 0xC2],
+    ///     [0xE7, 0xC2, 0xC3, 0xC4],
+    ///     [0xC5, 0xC6, 0xC7, 0xC8],
+    ///     2,
+    /// );
+    /// assert!(addresses.is_err());
+    /// ```
+    pub fn new_with_width(
+        address_width: AddressWidth,
+        base0: &[u8],
+        base1: &[u8],
+        prefixes0: [u8; 4],
+        prefixes1: [u8; 4],
+        rf_channel: u8,
     ) -> Result<Self, Error> {
         // TODO(AJM): Move to a builder pattern here?
-        if rf_channel > 100 {
+        let base_len = usize::from(address_width.base_len());
+        if rf_channel > 100 || base0.len() != base_len || base1.len() != base_len {
             return Err(Error::InvalidParameters);
         }
-        Ok(Self {
+        let (mut padded0, mut padded1) = ([0; 4], [0; 4]);
+        padded0[..base_len].copy_from_slice(base0);
+        padded1[..base_len].copy_from_slice(base1);
+
+        let addresses = Self {
+            base0: padded0,
+            base1: padded1,
+            prefixes0,
+            prefixes1,
+            rf_channel,
+            address_width,
+        };
+        if addresses.has_collisions() {
+            return Err(Error::InvalidParameters);
+        }
+        Ok(addresses)
+    }
+
+    /// Creates a new instance of `Addresses` like [new_with_width](#method.new_with_width),
+    /// rejecting weak addresses
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error in the same cases as
+    /// [new_with_width](#method.new_with_width), or if any pipe has a weak address, as defined by
+    /// [weak_pipes](#method.weak_pipes).
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use esb::{Addresses, AddressWidth};
+    ///
+    /// let addresses = Addresses::new_strict(
+    ///     AddressWidth::FiveBytes,
+    ///     &[0xE7, 0xE7, 0xE7, 0xE7],
+    ///     &[0xC2, 0xC2, 0xC2, 0xC2],
+    ///     [0xE7, 0xC2, 0xC3, 0xC4],
+    ///     [0xC5, 0xC6, 0xC7, 0xC8],
+    ///     2,
+    /// );
+    /// assert!(addresses.is_ok());
+    ///
+    /// let addresses = Addresses::new_strict(
+    ///     AddressWidth::FiveBytes,
+    ///     &[0xE7, 0xE7, 0xE7, 0xE7],
+    ///     &[0x00, 0x00, 0x00, 0x00],
+    ///     [0xE7, 0xC2, 0xC3, 0xC4],
+    ///     [0xC5, 0xC6, 0xC7, 0xC8],
+    ///     2,
+    /// );
+    /// assert!(addresses.is_err());
+    /// ```
+    pub fn new_strict(
+        address_width: AddressWidth,
+        base0: &[u8],
+        base1: &[u8],
+        prefixes0: [u8; 4],
+        prefixes1: [u8; 4],
+        rf_channel: u8,
+    ) -> Result<Self, Error> {
+        let addresses = Self::new_with_width(
+            address_width,
             base0,
             base1,
             prefixes0,
             prefixes1,
             rf_channel,
+        )?;
+        if addresses.weak_pipes() != 0 {
+            return Err(Error::InvalidParameters);
+        }
+        Ok(addresses)
+    }
+
+    /// Derives a set of addresses from a seed, such as the device ID in the FICR
+    ///
+    /// The same seed always gives the same addresses, so a PTX can derive the addresses of a PRX
+    /// from its ID. The generated addresses are different for every pipe and none of them is
+    /// weak, as defined by [weak_pipes](#method.weak_pipes).
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if `rf_channel` is bigger than 100.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use esb::{Addresses, AddressWidth};
+    ///
+    /// let addresses = Addresses::from_seed(AddressWidth::FiveBytes, 0x1234_5678_9ABC_DEF0, 2)
+    ///     .unwrap();
+    /// assert_eq!(addresses.weak_pipes(), 0);
+    ///
+    /// let same = Addresses::from_seed(AddressWidth::FiveBytes, 0x1234_5678_9ABC_DEF0, 2).unwrap();
+    /// assert_eq!(addresses.base0(), same.base0());
+    /// assert_eq!(addresses.prefixes1(), same.prefixes1());
+    /// ```
+    pub fn from_seed(
+        address_width: AddressWidth,
+        seed: u64,
+        rf_channel: u8,
+    ) -> Result<Self, Error> {
+        if rf_channel > 100 {
+            return Err(Error::InvalidParameters);
+        }
+        // xorshift64, the state can't be zero
+        let mut state = seed ^ 0x9E37_79B9_7F4A_7C15;
+        if state == 0 {
+            state = 0x9E37_79B9_7F4A_7C15;
+        }
+        let mut next_byte = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state >> 32) as u8
+        };
+
+        let base_len = usize::from(address_width.base_len());
+        let mut bases = [[0; 4]; 2];
+        for base in bases.iter_mut() {
+            loop {
+                for byte in base[..base_len].iter_mut() {
+                    *byte = next_byte();
+                }
+                let base = &base[..base_len];
+                if !is_preamble_like(base[base_len - 1]) && level_shifts(base) >= base_len as u32 {
+                    break;
+                }
+            }
+        }
+
+        let mut prefixes = [0; 8];
+        for pipe in 0..prefixes.len() {
+            let base = &bases[if pipe == 0 { 0 } else { 1 }][..base_len];
+            prefixes[pipe] = loop {
+                let prefix = next_byte();
+                let used = prefixes[..pipe]
+                    .iter()
+                    .enumerate()
+                    .any(|(other, &p)| p == prefix && (other != 0 || bases[0] == bases[1]));
+                if !used && !is_weak_address(prefix, base) {
+                    break prefix;
+                }
+            };
+        }
+
+        let [p0, p1, p2, p3, p4, p5, p6, p7] = prefixes;
+        Self::new_with_width(
+            address_width,
+            &bases[0][..base_len],
+            &bases[1][..base_len],
+            [p0, p1, p2, p3],
+            [p4, p5, p6, p7],
+            rf_channel,
+        )
+    }
+
+    /// Returns a bit mask of the pipes with weak addresses, the LSb is pipe zero
+    ///
+    /// Following Nordic's recommendations, an address is weak if it has fewer level shifts than
+    /// bytes, e.g. `0x00_00_00_FF_FF`, or if any of the bytes at its ends is `0x55` or `0xAA`,
+    /// which look like the preamble. Weak addresses increase the chance of false address matches
+    /// caused by noise.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use esb::Addresses;
+    ///
+    /// assert_eq!(Addresses::default().weak_pipes(), 0);
+    ///
+    /// let addresses = Addresses::new(
+    ///     [0x00, 0x00, 0x00, 0x00],
+    ///     [0xC2, 0xC2, 0xC2, 0xC2],
+    ///     [0xFF, 0xC2, 0xC3, 0xC4],
+    ///     [0xC5, 0xC6, 0xC7, 0xAA],
+    ///     2,
+    /// )
+    /// .unwrap();
+    /// assert_eq!(addresses.weak_pipes(), 0b1000_0001);
+    /// ```
+    pub fn weak_pipes(&self) -> u8 {
+        (0..8).fold(0, |mask, pipe| {
+            if is_weak_address(self.prefix(pipe), self.base(pipe)) {
+                mask | (1 << pipe)
+            } else {
+                mask
+            }
+        })
+    }
+
+    fn prefix(&self, pipe: usize) -> u8 {
+        if pipe < 4 {
+            self.prefixes0[pipe]
+        } else {
+            self.prefixes1[pipe - 4]
+        }
+    }
+
+    fn base(&self, pipe: usize) -> &[u8] {
+        let base = if pipe == 0 { &self.base0 } else { &self.base1 };
+        &base[..usize::from(self.address_width.base_len())]
+    }
+
+    // Checks if two pipes have the same address
+    fn has_collisions(&self) -> bool {
+        (0..8).any(|pipe| {
+            (pipe + 1..8).any(|other| {
+                self.prefix(pipe) == self.prefix(other) && self.base(pipe) == self.base(other)
+            })
         })
     }
 }
 
+// Bytes that look like the preamble
+fn is_preamble_like(byte: u8) -> bool {
+    byte == 0x55 || byte == 0xAA
+}
+
+// Counts the level shifts of the bit sequence formed by `bytes`
+fn level_shifts(bytes: &[u8]) -> u32 {
+    let bits = bytes
+        .iter()
+        .fold(0u64, |bits, &byte| (bits << 8) | u64::from(byte));
+    let mask = (1u64 << (bytes.len() * 8 - 1)) - 1;
+    ((bits ^ (bits >> 1)) & mask).count_ones()
+}
+
+fn is_weak_address(prefix: u8, base: &[u8]) -> bool {
+    let mut address = [prefix; 5];
+    address[1..=base.len()].copy_from_slice(base);
+    let address = &address[..=base.len()];
+    is_preamble_like(prefix)
+        || is_preamble_like(base[base.len() - 1])
+        || level_shifts(address) < address.len() as u32
+}
+
+impl Addresses {
+    /// Base address for pipe 0
+    pub fn base0(&self) -> [u8; 4] {
+        self.base0
+    }
+
+    /// Base address for pipe 1-7
+    pub fn base1(&self) -> [u8; 4] {
+        self.base1
+    }
+
+    /// Prefixes for pipes 0-3, in order
+    pub fn prefixes0(&self) -> [u8; 4] {
+        self.prefixes0
+    }
+
+    /// Prefixes for pipes 4-7, in order
+    pub fn prefixes1(&self) -> [u8; 4] {
+        self.prefixes1
+    }
+
+    /// Channel used by the radio hardware
+    pub fn rf_channel(&self) -> u8 {
+        self.rf_channel
+    }
+
+    /// Total width of the addresses
+    pub fn address_width(&self) -> AddressWidth {
+        self.address_width
+    }
+}
+
 impl Default for Addresses {
     fn default() -> Self {
         Self {
@@ -169,6 +761,320 @@ impl Default for Addresses {
             prefixes0: [0xE7, 0xC2, 0xC3, 0xC4],
             prefixes1: [0xC5, 0xC6, 0xC7, 0xC8],
             rf_channel: 2,
+            address_width: AddressWidth::FiveBytes,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bbqueue::{
+        consts::{U16, U64},
+        BBBuffer, ConstBBBuffer,
+    };
+
+    static CHANNEL_REQUEST: AtomicU16 = AtomicU16::new(0);
+    static TX_REPORTS: TxReports = TxReports::new();
+    static TX_CANCEL: TxCancel = TxCancel::new();
+    static STATS: EsbStats = EsbStats::new();
+
+    // An application interface on top of three test queues, with the default configuration
+    fn test_app<PriorityLen: ArrayLength<u8>>(
+        outgoing: &'static BBBuffer<U64>,
+        priority: &'static BBBuffer<PriorityLen>,
+        incoming: &'static BBBuffer<U64>,
+    ) -> EsbApp<U64, U64, PriorityLen> {
+        let (prod_to_radio, _) = outgoing.try_split_framed().unwrap();
+        let (prod_priority_to_radio, _) = priority.try_split_framed().unwrap();
+        let (_, cons_from_radio) = incoming.try_split_framed().unwrap();
+        EsbApp {
+            prod_to_radio,
+            prod_priority_to_radio,
+            cons_from_radio,
+            maximum_payload: 32,
+            pipe_maximum_payload: [32; 8],
+            payload_length: PayloadLength::Dynamic,
+            protocol: Protocol::EnhancedShockBurst,
+            channel_request: &CHANNEL_REQUEST,
+            channel_request_seq: 0,
+            tx_reports: &TX_REPORTS,
+            tx_cancel: &TX_CANCEL,
+            probe_seq: 0,
+            stats: &STATS,
+            stats_baseline: LinkStats::default(),
+            auto_pid: false,
+            next_pid: [0; 8],
+        }
+    }
+
+    const WIDTHS: [AddressWidth; 3] = [
+        AddressWidth::ThreeBytes,
+        AddressWidth::FourBytes,
+        AddressWidth::FiveBytes,
+    ];
+
+    #[test]
+    fn channel_request_round_trip() {
+        assert_eq!(
+            unpack_channel_request(pack_channel_request(1, 100)),
+            (1, 100)
+        );
+        assert_eq!(
+            unpack_channel_request(pack_channel_request(255, 0)),
+            (255, 0)
+        );
+        // The request stored when the buffer is split is never taken for the first request
+        assert_ne!(unpack_channel_request(0).0, 1);
+    }
+
+    #[test]
+    fn short_base_addresses_are_padded() {
+        let addresses = Addresses::new_with_width(
+            AddressWidth::ThreeBytes,
+            &[0x12, 0x34],
+            &[0x56, 0x78],
+            [0xE7, 0xC2, 0xC3, 0xC4],
+            [0xC5, 0xC6, 0xC7, 0xC8],
+            2,
+        )
+        .unwrap();
+        assert_eq!(addresses.base0, [0x12, 0x34, 0, 0]);
+        assert_eq!(addresses.base1, [0x56, 0x78, 0, 0]);
+        assert_eq!(addresses.address_width.base_len(), 2);
+    }
+
+    #[test]
+    fn base_length_must_match_the_width() {
+        let addresses = |width, base0: &[u8], base1: &[u8]| {
+            Addresses::new_with_width(
+                width,
+                base0,
+                base1,
+                [0xE7, 0xC2, 0xC3, 0xC4],
+                [0xC5, 0xC6, 0xC7, 0xC8],
+                2,
+            )
+        };
+        assert!(addresses(AddressWidth::FourBytes, &[1, 2, 3], &[4, 5, 6]).is_ok());
+        assert!(addresses(AddressWidth::FourBytes, &[1, 2, 3], &[4, 5]).is_err());
+        assert!(addresses(AddressWidth::FourBytes, &[1, 2, 3, 4], &[4, 5, 6]).is_err());
+        assert!(addresses(AddressWidth::FiveBytes, &[1, 2, 3, 4], &[4, 5, 6, 7]).is_ok());
+        assert!(addresses(AddressWidth::FiveBytes, &[], &[]).is_err());
+    }
+
+    #[test]
+    fn rf_channel_is_checked() {
+        let addresses = |channel| {
+            Addresses::new(
+                [0xE7, 0xE7, 0xE7, 0xE7],
+                [0xC2, 0xC2, 0xC2, 0xC2],
+                [0xE7, 0xC2, 0xC3, 0xC4],
+                [0xC5, 0xC6, 0xC7, 0xC8],
+                channel,
+            )
+        };
+        assert!(addresses(100).is_ok());
+        assert!(addresses(101).is_err());
+    }
+
+    #[test]
+    fn level_shifts_of_bytes() {
+        assert_eq!(level_shifts(&[0x00, 0x00]), 0);
+        assert_eq!(level_shifts(&[0x00, 0xFF]), 1);
+        assert_eq!(level_shifts(&[0x55]), 7);
+        assert_eq!(level_shifts(&[0xF0, 0x0F]), 2);
+    }
+
+    #[test]
+    fn from_seed_gives_strong_unique_addresses() {
+        // The last seed zeroes the generator state before its fallback
+        let seeds = [0, 1, 0x1234_5678, u64::MAX, 0x9E37_79B9_7F4A_7C15];
+        for &width in WIDTHS.iter() {
+            for &seed in seeds.iter() {
+                let addresses = Addresses::from_seed(width, seed, 40).unwrap();
+                assert_eq!(addresses.weak_pipes(), 0);
+                assert!(!addresses.has_collisions());
+                assert_eq!(addresses.rf_channel(), 40);
+                // Bytes past the base length are never used
+                let base_len = usize::from(width.base_len());
+                assert!(addresses.base0()[base_len..].iter().all(|&byte| byte == 0));
+                assert!(addresses.base1()[base_len..].iter().all(|&byte| byte == 0));
+            }
         }
     }
+
+    #[test]
+    fn from_seed_is_deterministic() {
+        let a = Addresses::from_seed(AddressWidth::FourBytes, 42, 2).unwrap();
+        let b = Addresses::from_seed(AddressWidth::FourBytes, 42, 2).unwrap();
+        let c = Addresses::from_seed(AddressWidth::FourBytes, 43, 2).unwrap();
+        assert_eq!((a.base0(), a.base1()), (b.base0(), b.base1()));
+        assert_eq!(
+            (a.prefixes0(), a.prefixes1()),
+            (b.prefixes0(), b.prefixes1())
+        );
+        assert_ne!(
+            (a.base0(), a.base1(), a.prefixes0(), a.prefixes1()),
+            (c.base0(), c.base1(), c.prefixes0(), c.prefixes1())
+        );
+    }
+
+    #[test]
+    fn from_seed_rejects_bad_channel() {
+        assert!(Addresses::from_seed(AddressWidth::FiveBytes, 42, 101).is_err());
+    }
+
+    #[test]
+    fn weak_pipes_of_short_addresses() {
+        let addresses = Addresses::new_with_width(
+            AddressWidth::ThreeBytes,
+            &[0x12, 0x34],
+            &[0xC3, 0x55],
+            [0x00, 0x5A, 0xA5, 0x3C],
+            [0xC5, 0xC6, 0xC7, 0xC8],
+            2,
+        )
+        .unwrap();
+        // Pipes 1-7 share a base ending with a preamble-like byte
+        assert_eq!(addresses.weak_pipes(), 0b1111_1110);
+    }
+
+    #[test]
+    fn strict_constructor_rejects_weak_pipes() {
+        let strict = Addresses::new_strict(
+            AddressWidth::ThreeBytes,
+            &[0x12, 0x34],
+            &[0xC3, 0x55],
+            [0x00, 0x5A, 0xA5, 0x3C],
+            [0xC5, 0xC6, 0xC7, 0xC8],
+            2,
+        );
+        assert!(matches!(strict, Err(Error::InvalidParameters)));
+        let seeded = Addresses::from_seed(AddressWidth::ThreeBytes, 42, 2).unwrap();
+        let strict = Addresses::new_strict(
+            AddressWidth::ThreeBytes,
+            &seeded.base0()[..2],
+            &seeded.base1()[..2],
+            seeded.prefixes0(),
+            seeded.prefixes1(),
+            2,
+        );
+        assert!(strict.is_ok());
+    }
+
+    #[test]
+    fn collisions_are_rejected() {
+        assert!(Addresses::new(
+            [0xC2, 0xC2, 0xC2, 0xC2],
+            [0xC2, 0xC2, 0xC2, 0xC2],
+            [0xC2, 0xC2, 0xC3, 0xC4],
+            [0xC5, 0xC6, 0xC7, 0xC8],
+            2,
+        )
+        .is_err());
+        assert!(Addresses::new(
+            [0xE7, 0xE7, 0xE7, 0xE7],
+            [0xC2, 0xC2, 0xC2, 0xC2],
+            [0xC2, 0xC2, 0xC3, 0xC4],
+            [0xC5, 0xC6, 0xC7, 0xC8],
+            2,
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn auto_pid_counts_per_pipe() {
+        static OUTGOING: BBBuffer<U64> = BBBuffer(ConstBBBuffer::new());
+        static PRIORITY: BBBuffer<U0> = BBBuffer(ConstBBBuffer::new());
+        static INCOMING: BBBuffer<U64> = BBBuffer(ConstBBBuffer::new());
+        let mut app = test_app(&OUTGOING, &PRIORITY, &INCOMING);
+        let pid = |app: &mut EsbApp<U64, U64>, pipe| {
+            let header = EsbHeader::new(4, 3, pipe, false).unwrap();
+            // Dropping the grant doesn't give the pid back
+            app.grant_packet(header).unwrap().pid()
+        };
+
+        // The pid of the header is kept while disabled
+        assert!(!app.auto_pid());
+        assert_eq!(pid(&mut app, 1), 3);
+
+        app.set_auto_pid(true);
+        let pids: [u8; 6] = [0, 1, 2, 3, 0, 1];
+        for &expected in pids.iter() {
+            assert_eq!(pid(&mut app, 1), expected);
+        }
+        // Each pipe has its own counter
+        assert_eq!(pid(&mut app, 2), 0);
+        assert_eq!(pid(&mut app, 1), 2);
+
+        // A rejected grant doesn't consume a pid
+        let too_long = EsbHeader::new(33, 0, 2, false).unwrap();
+        assert!(app.grant_packet(too_long).is_err());
+        assert_eq!(pid(&mut app, 2), 1);
+    }
+
+    #[test]
+    fn static_length_is_checked_per_pipe() {
+        static OUTGOING: BBBuffer<U64> = BBBuffer(ConstBBBuffer::new());
+        static PRIORITY: BBBuffer<U0> = BBBuffer(ConstBBBuffer::new());
+        static INCOMING: BBBuffer<U64> = BBBuffer(ConstBBBuffer::new());
+        let mut app = test_app(&OUTGOING, &PRIORITY, &INCOMING);
+        app.payload_length = PayloadLength::Static(16);
+        app.maximum_payload = 16;
+        app.pipe_maximum_payload = [16, 8, 16, 16, 16, 16, 16, 16];
+        let header = |length, pipe| EsbHeader::new(length, 0, pipe, false).unwrap();
+
+        assert!(app.grant_packet(header(16, 0)).is_ok());
+        assert_eq!(
+            app.grant_packet(header(8, 0)).err(),
+            Some(Error::InvalidParameters)
+        );
+        // Pipe 1 has a shorter static length
+        assert_eq!(app.grant_packet(header(8, 1)).unwrap().payload_len(), 8);
+        assert_eq!(
+            app.grant_packet(header(16, 1)).err(),
+            Some(Error::MaximumPacketExceeded)
+        );
+    }
+
+    #[test]
+    fn priority_packets_use_their_own_queue() {
+        static OUTGOING: BBBuffer<U64> = BBBuffer(ConstBBBuffer::new());
+        static PRIORITY: BBBuffer<U16> = BBBuffer(ConstBBBuffer::new());
+        static INCOMING: BBBuffer<U64> = BBBuffer(ConstBBBuffer::new());
+        let mut app = test_app(&OUTGOING, &PRIORITY, &INCOMING);
+        let header = EsbHeader::new(8, 0, 0, false).unwrap();
+
+        // A grant in progress in one queue doesn't block the other
+        let normal = app.grant_packet(header).unwrap();
+        let urgent = app.grant_priority_packet(header).unwrap();
+        urgent.commit(8);
+        normal.commit(8);
+        assert_eq!(
+            app.grant_priority_packet(header).err(),
+            Some(Error::OutgoingQueueFull)
+        );
+        assert!(app.grant_packet(header).is_ok());
+        // The checks of the normal queue also apply
+        let too_long = EsbHeader::new(33, 0, 0, false).unwrap();
+        assert_eq!(
+            app.grant_priority_packet(too_long).err(),
+            Some(Error::MaximumPacketExceeded)
+        );
+    }
+
+    #[test]
+    fn priority_queue_is_empty_by_default() {
+        static OUTGOING: BBBuffer<U64> = BBBuffer(ConstBBBuffer::new());
+        static PRIORITY: BBBuffer<U0> = BBBuffer(ConstBBBuffer::new());
+        static INCOMING: BBBuffer<U64> = BBBuffer(ConstBBBuffer::new());
+        let mut app = test_app(&OUTGOING, &PRIORITY, &INCOMING);
+        let header = EsbHeader::new(0, 0, 0, false).unwrap();
+        assert_eq!(
+            app.grant_priority_packet(header).err(),
+            Some(Error::OutgoingQueueFull)
+        );
+        assert!(app.grant_packet(header).is_ok());
+    }
 }
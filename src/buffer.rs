@@ -1,13 +1,17 @@
 use crate::{
     app::{Addresses, EsbApp},
+    cancel::TxCancel,
     irq::{Disabled, EsbIrq, IrqTimer},
     peripherals::{EsbRadio, EsbTimer, RADIO},
+    random::XorShift32,
+    report::TxReports,
+    stats::{EsbStats, LinkStats},
     Config, Error,
 };
-use bbqueue::{ArrayLength, BBBuffer};
+use bbqueue::{consts::U0, ArrayLength, BBBuffer, ConstBBBuffer};
 use core::{
     marker::PhantomData,
-    sync::atomic::{AtomicBool, Ordering},
+    sync::atomic::{AtomicBool, AtomicU16, Ordering},
 };
 
 /// This is the backing structure for the ESB interface
@@ -17,47 +21,63 @@ use core::{
 ///
 /// ## Creating at static scope
 ///
-/// Currently due to lacking const generics, the UX for this
-/// isn't great. You'll probably want something like this:
-///
-/// ## NOTE
-///
-/// Although the members of this struct are public, due to const
-/// generic limitations, they are not intended to be used directly,
-/// outside of `static` creation.
-///
-/// This could cause unintended, though not undefined, behavior.
-///
-/// TL;DR: It's not unsafe, but it's also not going to work correctly.
+/// The buffer is created with the [`new`](#method.new) const constructor, the sizes of the queues
+/// are given by the type parameters.
 ///
 /// ```rust
 /// // This creates an ESB storage structure with room for
 /// // 512 bytes of outgoing packets (including headers),
 /// // and 256 bytes of incoming packets (including
 /// // headers).
-/// # use esb::{BBBuffer, consts::*, ConstBBBuffer, EsbBuffer};
-/// # use core::sync::atomic::AtomicBool;
-/// static BUFFER: EsbBuffer<U512, U256> = EsbBuffer {
-///     app_to_radio_buf: BBBuffer( ConstBBBuffer::new() ),
-///     radio_to_app_buf: BBBuffer( ConstBBBuffer::new() ),
-///     timer_flag: AtomicBool::new(false),
-/// };
+/// # use esb::{consts::*, EsbBuffer};
+/// static BUFFER: EsbBuffer<U512, U256> = EsbBuffer::new();
+/// ```
+///
+/// The outgoing priority queue is optional and empty by default, its size is given by the
+/// third type parameter, see
+/// [`EsbApp::grant_priority_packet`](../app/struct.EsbApp.html#method.grant_priority_packet).
+///
+/// ```rust
+/// // Same as above, with room for 128 bytes of urgent outgoing packets
+/// # use esb::{consts::*, EsbBuffer};
+/// static BUFFER: EsbBuffer<U512, U256, U128> = EsbBuffer::new();
 /// ```
-pub struct EsbBuffer<OutgoingLen, IncomingLen>
+pub struct EsbBuffer<OutgoingLen, IncomingLen, PriorityLen = U0>
 where
     OutgoingLen: ArrayLength<u8>,
     IncomingLen: ArrayLength<u8>,
+    PriorityLen: ArrayLength<u8>,
 {
-    pub app_to_radio_buf: BBBuffer<OutgoingLen>,
-    pub radio_to_app_buf: BBBuffer<IncomingLen>,
-    pub timer_flag: AtomicBool,
+    app_to_radio_buf: BBBuffer<OutgoingLen>,
+    app_to_radio_priority_buf: BBBuffer<PriorityLen>,
+    radio_to_app_buf: BBBuffer<IncomingLen>,
+    timer_flag: AtomicBool,
+    channel_request: AtomicU16,
+    tx_reports: TxReports,
+    tx_cancel: TxCancel,
+    stats: EsbStats,
 }
 
-impl<OutgoingLen, IncomingLen> EsbBuffer<OutgoingLen, IncomingLen>
+impl<OutgoingLen, IncomingLen, PriorityLen> EsbBuffer<OutgoingLen, IncomingLen, PriorityLen>
 where
     OutgoingLen: ArrayLength<u8>,
     IncomingLen: ArrayLength<u8>,
+    PriorityLen: ArrayLength<u8>,
 {
+    /// Creates the storage, meant for `static` creation
+    pub const fn new() -> Self {
+        Self {
+            app_to_radio_buf: BBBuffer(ConstBBBuffer::new()),
+            app_to_radio_priority_buf: BBBuffer(ConstBBBuffer::new()),
+            radio_to_app_buf: BBBuffer(ConstBBBuffer::new()),
+            timer_flag: AtomicBool::new(false),
+            channel_request: AtomicU16::new(0),
+            tx_reports: TxReports::new(),
+            tx_cancel: TxCancel::new(),
+            stats: EsbStats::new(),
+        }
+    }
+
     /// Attempt to split the `static` buffer into handles for Interrupt and App context
     ///
     /// This function will only succeed once. If the underlying buffers have also
@@ -74,8 +94,8 @@ where
         config: Config,
     ) -> Result<
         (
-            EsbApp<OutgoingLen, IncomingLen>,
-            EsbIrq<OutgoingLen, IncomingLen, T, Disabled>,
+            EsbApp<OutgoingLen, IncomingLen, PriorityLen>,
+            EsbIrq<OutgoingLen, IncomingLen, T, Disabled, PriorityLen>,
             IrqTimer<T>,
         ),
         Error,
@@ -88,26 +108,62 @@ where
             .radio_to_app_buf
             .try_split_framed()
             .map_err(|_| Error::AlreadySplit)?;
+        let (atr_priority_prod, atr_priority_cons) = self
+            .app_to_radio_priority_buf
+            .try_split_framed()
+            .map_err(|_| Error::AlreadySplit)?;
 
-        // Clear the timer flag
+        // Clear the timer flag, any channel request, any report, the cancellations and the
+        // statistics
         self.timer_flag.store(false, Ordering::Release);
+        self.channel_request.store(0, Ordering::Release);
+        self.tx_reports.clear();
+        self.tx_cancel.clear();
+        self.stats.clear();
 
         let app = EsbApp {
             prod_to_radio: atr_prod,
+            prod_priority_to_radio: atr_priority_prod,
             cons_from_radio: rta_cons,
             maximum_payload: config.maximum_payload_size,
+            pipe_maximum_payload: config.pipe_maximum_payload_sizes(),
+            payload_length: config.payload_length,
+            protocol: config.protocol,
+            channel_request: &self.channel_request,
+            channel_request_seq: 0,
+            tx_reports: &self.tx_reports,
+            tx_cancel: &self.tx_cancel,
+            probe_seq: 0,
+            stats: &self.stats,
+            stats_baseline: LinkStats::default(),
+            auto_pid: false,
+            next_pid: [0; 8],
         };
 
         let mut irq = EsbIrq {
             prod_to_app: rta_prod,
             cons_from_app: atr_cons,
+            cons_priority_from_app: atr_priority_cons,
+            tx_priority: false,
             timer,
             radio: EsbRadio::new(radio),
             state: Disabled,
             addresses,
             attempts: 0,
+            busy_assessments: 0,
             timer_flag: &self.timer_flag,
             config,
+            pending_channel: None,
+            channel_request: &self.channel_request,
+            channel_request_seq: 0,
+            hopper: None,
+            poller: None,
+            tx_reports: &self.tx_reports,
+            backoff_rng: XorShift32::default(),
+            clock: None,
+            tx_cancel: &self.tx_cancel,
+            probe_time: 0,
+            stats: &self.stats,
         };
 
         let irq_timer = IrqTimer {
@@ -115,13 +171,21 @@ where
             _timer: PhantomData,
         };
 
-        irq.radio.init(
-            irq.config.maximum_payload_size,
-            irq.config.tx_power,
-            &irq.addresses,
-        );
+        irq.radio.init(&irq.config, &irq.addresses);
         irq.timer.init();
 
         Ok((app, irq, irq_timer))
     }
 }
+
+impl<OutgoingLen, IncomingLen, PriorityLen> Default
+    for EsbBuffer<OutgoingLen, IncomingLen, PriorityLen>
+where
+    OutgoingLen: ArrayLength<u8>,
+    IncomingLen: ArrayLength<u8>,
+    PriorityLen: ArrayLength<u8>,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,156 @@
+//! Cancellation of queued outgoing packets
+//!
+//! A committed packet can't be taken out of the middle of the outgoing queue, instead each pipe
+//! has an epoch that is written in the header of its packets by
+//! [EsbApp](../app/struct.EsbApp.html) when they are granted.
+//! [EsbApp::cancel](../app/struct.EsbApp.html#method.cancel) moves the epoch of the given pipes
+//! forward, and [EsbIrq](../irq/struct.EsbIrq.html) discards the packets of an older epoch when
+//! they reach the head of the queue, instead of sending them. A packet being transmitted when
+//! the cancellation is requested finishes its current attempt, but is not retransmitted.
+//!
+//! In PTX mode, the cancellation pends the radio interrupt, which discards the cancelled packets
+//! right away if the PTX is idle, or at the end of the current attempt otherwise. A
+//! [flush](../app/struct.EsbApp.html#method.flush) is complete once the PTX is idle again. In PRX
+//! mode, the interrupt isn't pended, the cancelled packets are discarded when the PRX prepares
+//! its next acknowledgements.
+//!
+//! The epochs are 7 bits wide, they share a byte of the in-queue header with the RSSI, so a
+//! packet is only kept by mistake if its pipe is cancelled 128 times while it waits in the queue.
+
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU8, Ordering};
+
+/// Bits of the epochs
+const EPOCH_MASK: u8 = 0b0111_1111;
+
+/// State shared by [EsbApp](../app/struct.EsbApp.html) and [EsbIrq](../irq/struct.EsbIrq.html)
+/// to cancel outgoing packets
+///
+/// Only atomic loads and stores are needed, the application is the only writer of the epochs
+/// and the interrupt is the only writer of the discarded count and of the mode.
+pub(crate) struct TxCancel {
+    epochs: [AtomicU8; 8],
+    discarded: AtomicU32,
+    ptx: AtomicBool,
+}
+
+impl TxCancel {
+    /// Creates the cancellation state, used for the `static` creation of
+    /// [`EsbBuffer`](../buffer/struct.EsbBuffer.html)
+    #[allow(clippy::declare_interior_mutable_const)]
+    pub(crate) const fn new() -> Self {
+        const EPOCH: AtomicU8 = AtomicU8::new(0);
+        Self {
+            epochs: [EPOCH; 8],
+            discarded: AtomicU32::new(0),
+            ptx: AtomicBool::new(false),
+        }
+    }
+
+    /// Resets the state, must only be called before it is shared
+    pub(crate) fn clear(&self) {
+        for epoch in self.epochs.iter() {
+            epoch.store(0, Ordering::Relaxed);
+        }
+        self.discarded.store(0, Ordering::Release);
+        self.ptx.store(false, Ordering::Release);
+    }
+
+    /// Current epoch of `pipe`
+    pub(crate) fn epoch(&self, pipe: u8) -> u8 {
+        self.epochs[usize::from(pipe)].load(Ordering::Acquire)
+    }
+
+    /// Moves the epoch of the pipes in the `pipes` bit mask forward, only called by the
+    /// application
+    pub(crate) fn cancel(&self, pipes: u8) {
+        for (pipe, epoch) in self.epochs.iter().enumerate() {
+            if pipes & (1 << pipe) != 0 {
+                epoch.store(
+                    epoch.load(Ordering::Relaxed).wrapping_add(1) & EPOCH_MASK,
+                    Ordering::Release,
+                );
+            }
+        }
+    }
+
+    /// Whether a packet of `pipe` granted in `epoch` was cancelled
+    pub(crate) fn is_cancelled(&self, pipe: u8, epoch: u8) -> bool {
+        self.epoch(pipe) != epoch
+    }
+
+    /// Counts a discarded packet, only called by the interrupt
+    pub(crate) fn count_discarded(&self) {
+        self.discarded.store(
+            self.discarded.load(Ordering::Relaxed).wrapping_add(1),
+            Ordering::Release,
+        );
+    }
+
+    /// Number of packets discarded since the buffer was split, it wraps around
+    pub(crate) fn discarded(&self) -> u32 {
+        self.discarded.load(Ordering::Acquire)
+    }
+
+    /// Records whether the interrupt is in PTX mode, only called by the interrupt
+    pub(crate) fn set_ptx(&self, ptx: bool) {
+        self.ptx.store(ptx, Ordering::Release);
+    }
+
+    /// Whether a cancellation must pend the radio interrupt, only in PTX mode
+    pub(crate) fn ptx(&self) -> bool {
+        self.ptx.load(Ordering::Acquire)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cancel_moves_selected_epochs() {
+        let cancel = TxCancel::new();
+        cancel.cancel(0b1000_0101);
+        for pipe in 0..8 {
+            let expected = if [0, 2, 7].contains(&pipe) { 1 } else { 0 };
+            assert_eq!(cancel.epoch(pipe), expected);
+        }
+        assert!(cancel.is_cancelled(0, 0));
+        assert!(!cancel.is_cancelled(0, 1));
+        assert!(!cancel.is_cancelled(1, 0));
+    }
+
+    #[test]
+    fn epochs_wrap_at_seven_bits() {
+        let cancel = TxCancel::new();
+        for expected in 1..=EPOCH_MASK {
+            cancel.cancel(0b1);
+            assert_eq!(cancel.epoch(0), expected);
+        }
+        // The epoch must never set the MSb, which flags the in-queue header extension
+        cancel.cancel(0b1);
+        assert_eq!(cancel.epoch(0), 0);
+        assert!(!cancel.is_cancelled(0, 0));
+    }
+
+    #[test]
+    fn discarded_count_and_clear() {
+        let cancel = TxCancel::new();
+        cancel.cancel(0xFF);
+        cancel.count_discarded();
+        cancel.count_discarded();
+        assert_eq!(cancel.discarded(), 2);
+        cancel.clear();
+        assert_eq!(cancel.discarded(), 0);
+        assert!((0..8).all(|pipe| cancel.epoch(pipe) == 0));
+    }
+
+    #[test]
+    fn mode_is_cleared_with_the_buffer() {
+        let cancel = TxCancel::new();
+        assert!(!cancel.ptx());
+        cancel.set_ptx(true);
+        assert!(cancel.ptx());
+        cancel.clear();
+        assert!(!cancel.ptx());
+    }
+}
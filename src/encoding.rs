@@ -0,0 +1,613 @@
+//! Versioned binary encoding of [Config](../struct.Config.html) and
+//! [Addresses](../app/struct.Addresses.html), and their optional `serde` support
+//!
+//! Every encoding starts with a version byte, decoding always goes through the same validation as
+//! the constructors.
+
+use crate::{
+    app::{AddressWidth, Addresses},
+    Backoff, Cca, Config, ConfigBuilder, Crc, DataRate, Error, PayloadLength, PipeConfig, Protocol,
+    RampUp, TxPower,
+};
+
+/// Current version of the `Config` encoding
+const CONFIG_VERSION: u8 = 1;
+
+/// Current version of the `Addresses` encoding
+const ADDRESSES_VERSION: u8 = 1;
+
+// Config encoding, version 1:
+//
+// | version | wait_for_ack_timeout (LE) | retransmit_delay (LE) | maximum_transmit_attempts |
+// | enabled_pipes | tx_power (signed dBm) | maximum_payload_size | data_rate | crc |
+// | payload_length mode | static length | protocol | 8 x (pipe flags | pipe maximum payload size) |
+// | ramp-up flags | backoff flags | backoff jitter (LE) | backoff maximum delay (LE) |
+// | cca flags | cca threshold | cca samples | cca busy delay (LE) | cca maximum busy |
+const PIPES_OFFSET: usize = 14;
+const PIPE_AUTO_ACK: u8 = 1 << 0;
+const PIPE_MAXIMUM_PAYLOAD: u8 = 1 << 1;
+const RAMP_UP_OFFSET: usize = PIPES_OFFSET + 2 * 8;
+const RAMP_UP_FAST: u8 = 1 << 0;
+const PEER_RAMP_UP_FAST: u8 = 1 << 1;
+const BACKOFF_OFFSET: usize = RAMP_UP_OFFSET + 1;
+const BACKOFF_ENABLED: u8 = 1 << 0;
+const BACKOFF_EXPONENTIAL: u8 = 1 << 1;
+const CCA_OFFSET: usize = BACKOFF_OFFSET + 5;
+const CCA_ENABLED: u8 = 1 << 0;
+
+impl Config {
+    /// Size of the binary encoding, in bytes
+    pub const ENCODED_SIZE: usize = CCA_OFFSET + 6;
+
+    /// Encodes the configuration into a compact and versioned binary format
+    ///
+    /// The tx power is encoded in dBm, so the encoding can be decoded on another device as long as
+    /// it supports the same tx power.
+    pub fn to_bytes(&self) -> [u8; Config::ENCODED_SIZE] {
+        let mut bytes = [0; Config::ENCODED_SIZE];
+        let (length_mode, static_length) = match self.payload_length {
+            PayloadLength::Dynamic => (0, 0),
+            PayloadLength::Static(length) => (1, length),
+        };
+        bytes[0] = CONFIG_VERSION;
+        bytes[1..3].copy_from_slice(&self.wait_for_ack_timeout.to_le_bytes());
+        bytes[3..5].copy_from_slice(&self.retransmit_delay.to_le_bytes());
+        bytes[5] = self.maximum_transmit_attempts;
+        bytes[6] = self.enabled_pipes;
+        bytes[7] = tx_power_to_dbm(self.tx_power) as u8;
+        bytes[8] = self.maximum_payload_size;
+        bytes[9] = data_rate_to_bits(self.data_rate);
+        bytes[10] = match self.crc {
+            Crc::Disabled => 0,
+            Crc::OneByte => 1,
+            Crc::TwoBytes => 2,
+        };
+        bytes[11] = length_mode;
+        bytes[12] = static_length;
+        bytes[13] = match self.protocol {
+            Protocol::EnhancedShockBurst => 0,
+            Protocol::ShockBurst => 1,
+        };
+        for (chunk, pipe) in bytes[PIPES_OFFSET..]
+            .chunks_exact_mut(2)
+            .zip(self.pipes.iter())
+        {
+            let mut flags = 0;
+            if pipe.auto_ack() {
+                flags |= PIPE_AUTO_ACK;
+            }
+            if let Some(size) = pipe.maximum_payload_size() {
+                flags |= PIPE_MAXIMUM_PAYLOAD;
+                chunk[1] = size;
+            }
+            chunk[0] = flags;
+        }
+        if self.ramp_up == RampUp::Fast {
+            bytes[RAMP_UP_OFFSET] |= RAMP_UP_FAST;
+        }
+        if self.peer_ramp_up == RampUp::Fast {
+            bytes[RAMP_UP_OFFSET] |= PEER_RAMP_UP_FAST;
+        }
+        if let Some(backoff) = self.backoff {
+            bytes[BACKOFF_OFFSET] = BACKOFF_ENABLED;
+            if backoff.exponential() {
+                bytes[BACKOFF_OFFSET] |= BACKOFF_EXPONENTIAL;
+            }
+            bytes[BACKOFF_OFFSET + 1..BACKOFF_OFFSET + 3]
+                .copy_from_slice(&backoff.jitter().to_le_bytes());
+            bytes[BACKOFF_OFFSET + 3..BACKOFF_OFFSET + 5]
+                .copy_from_slice(&backoff.maximum_delay().to_le_bytes());
+        }
+        if let Some(cca) = self.cca {
+            bytes[CCA_OFFSET] = CCA_ENABLED;
+            bytes[CCA_OFFSET + 1] = cca.threshold();
+            bytes[CCA_OFFSET + 2] = cca.samples();
+            bytes[CCA_OFFSET + 3..CCA_OFFSET + 5].copy_from_slice(&cca.busy_delay().to_le_bytes());
+            bytes[CCA_OFFSET + 5] = cca.maximum_busy();
+        }
+        bytes
+    }
+
+    /// Decodes a configuration encoded by [to_bytes](#method.to_bytes)
+    ///
+    /// # Errors
+    ///
+    /// An error will be returned if the version is unknown, if the length of `bytes` doesn't
+    /// match the version, if a field has an invalid value or if the configuration doesn't pass
+    /// the validation of [ConfigBuilder](struct.ConfigBuilder.html).
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use esb::{Config, ConfigBuilder, PayloadLength};
+    ///
+    /// let config = ConfigBuilder::default()
+    ///     .payload_length(PayloadLength::Static(32))
+    ///     .check()
+    ///     .unwrap();
+    /// let bytes = config.to_bytes();
+    /// let decoded = Config::from_bytes(&bytes).unwrap();
+    /// assert_eq!(decoded.payload_length(), PayloadLength::Static(32));
+    /// assert_eq!(decoded.to_bytes(), bytes);
+    ///
+    /// assert!(Config::from_bytes(&bytes[..10]).is_err());
+    /// ```
+    pub fn from_bytes(bytes: &[u8]) -> Result<Config, Error> {
+        if bytes.len() != Config::ENCODED_SIZE || bytes[0] != CONFIG_VERSION {
+            return Err(Error::InvalidParameters);
+        }
+        let crc = match bytes[10] {
+            0 => Crc::Disabled,
+            1 => Crc::OneByte,
+            2 => Crc::TwoBytes,
+            _ => return Err(Error::InvalidParameters),
+        };
+        let payload_length = match bytes[11] {
+            0 => PayloadLength::Dynamic,
+            1 => PayloadLength::Static(bytes[12]),
+            _ => return Err(Error::InvalidParameters),
+        };
+        let protocol = match bytes[13] {
+            0 => Protocol::EnhancedShockBurst,
+            1 => Protocol::ShockBurst,
+            _ => return Err(Error::InvalidParameters),
+        };
+        let mut pipes = [PipeConfig::default(); 8];
+        for (pipe, chunk) in pipes.iter_mut().zip(bytes[PIPES_OFFSET..].chunks_exact(2)) {
+            if chunk[0] & !(PIPE_AUTO_ACK | PIPE_MAXIMUM_PAYLOAD) != 0 {
+                return Err(Error::InvalidParameters);
+            }
+            let size = if chunk[0] & PIPE_MAXIMUM_PAYLOAD != 0 {
+                Some(chunk[1])
+            } else {
+                None
+            };
+            *pipe = PipeConfig::new(chunk[0] & PIPE_AUTO_ACK != 0, size);
+        }
+        let ramp_up_flags = bytes[RAMP_UP_OFFSET];
+        if ramp_up_flags & !(RAMP_UP_FAST | PEER_RAMP_UP_FAST) != 0 {
+            return Err(Error::InvalidParameters);
+        }
+        let backoff_flags = bytes[BACKOFF_OFFSET];
+        if backoff_flags & !(BACKOFF_ENABLED | BACKOFF_EXPONENTIAL) != 0
+            || (backoff_flags & BACKOFF_ENABLED == 0 && backoff_flags != 0)
+        {
+            return Err(Error::InvalidParameters);
+        }
+        let backoff = if backoff_flags & BACKOFF_ENABLED != 0 {
+            Some(Backoff::new(
+                u16::from_le_bytes([bytes[BACKOFF_OFFSET + 1], bytes[BACKOFF_OFFSET + 2]]),
+                backoff_flags & BACKOFF_EXPONENTIAL != 0,
+                u16::from_le_bytes([bytes[BACKOFF_OFFSET + 3], bytes[BACKOFF_OFFSET + 4]]),
+            ))
+        } else {
+            None
+        };
+        let cca = match bytes[CCA_OFFSET] {
+            0 => None,
+            CCA_ENABLED => Some(Cca::new(
+                bytes[CCA_OFFSET + 1],
+                bytes[CCA_OFFSET + 2],
+                u16::from_le_bytes([bytes[CCA_OFFSET + 3], bytes[CCA_OFFSET + 4]]),
+                bytes[CCA_OFFSET + 5],
+            )),
+            _ => return Err(Error::InvalidParameters),
+        };
+
+        ConfigBuilder::default()
+            .wait_for_ack_timeout(u16::from_le_bytes([bytes[1], bytes[2]]))
+            .retransmit_delay(u16::from_le_bytes([bytes[3], bytes[4]]))
+            .maximum_transmit_attempts(bytes[5])
+            .enabled_pipes(bytes[6])
+            .tx_power(tx_power_from_dbm(bytes[7] as i8).ok_or(Error::InvalidParameters)?)
+            .max_payload_size(bytes[8])
+            .data_rate(data_rate_from_bits(bytes[9]).ok_or(Error::InvalidParameters)?)
+            .crc(crc)
+            .payload_length(payload_length)
+            .protocol(protocol)
+            .pipes(pipes)
+            .ramp_up(ramp_up_from_flag(ramp_up_flags & RAMP_UP_FAST != 0))
+            .peer_ramp_up(ramp_up_from_flag(ramp_up_flags & PEER_RAMP_UP_FAST != 0))
+            .backoff(backoff)
+            .cca(cca)
+            .check()
+    }
+}
+
+impl Addresses {
+    /// Size of the binary encoding, in bytes
+    pub const ENCODED_SIZE: usize = 19;
+
+    /// Encodes the addresses into a compact and versioned binary format
+    pub fn to_bytes(&self) -> [u8; Addresses::ENCODED_SIZE] {
+        let mut bytes = [0; Addresses::ENCODED_SIZE];
+        bytes[0] = ADDRESSES_VERSION;
+        bytes[1] = address_width_to_bits(self.address_width);
+        bytes[2..6].copy_from_slice(&self.base0);
+        bytes[6..10].copy_from_slice(&self.base1);
+        bytes[10..14].copy_from_slice(&self.prefixes0);
+        bytes[14..18].copy_from_slice(&self.prefixes1);
+        bytes[18] = self.rf_channel;
+        bytes
+    }
+
+    /// Decodes addresses encoded by [to_bytes](#method.to_bytes)
+    ///
+    /// # Errors
+    ///
+    /// An error will be returned if the version is unknown, if the length of `bytes` doesn't
+    /// match the version or if the addresses don't pass the validation of
+    /// [new_with_width](#method.new_with_width).
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use esb::Addresses;
+    ///
+    /// let bytes = Addresses::default().to_bytes();
+    /// let decoded = Addresses::from_bytes(&bytes).unwrap();
+    /// assert_eq!(decoded.base0(), Addresses::default().base0());
+    /// assert_eq!(decoded.to_bytes(), bytes);
+    /// ```
+    pub fn from_bytes(bytes: &[u8]) -> Result<Addresses, Error> {
+        if bytes.len() != Addresses::ENCODED_SIZE || bytes[0] != ADDRESSES_VERSION {
+            return Err(Error::InvalidParameters);
+        }
+        let address_width = address_width_from_bits(bytes[1]).ok_or(Error::InvalidParameters)?;
+        let base_len = usize::from(address_width.base_len());
+        let mut prefixes = [[0; 4]; 2];
+        prefixes[0].copy_from_slice(&bytes[10..14]);
+        prefixes[1].copy_from_slice(&bytes[14..18]);
+        Addresses::new_with_width(
+            address_width,
+            &bytes[2..2 + base_len],
+            &bytes[6..6 + base_len],
+            prefixes[0],
+            prefixes[1],
+            bytes[18],
+        )
+    }
+}
+
+fn ramp_up_from_flag(fast: bool) -> RampUp {
+    if fast {
+        RampUp::Fast
+    } else {
+        RampUp::Default
+    }
+}
+
+fn data_rate_to_bits(data_rate: DataRate) -> u8 {
+    match data_rate {
+        DataRate::Nrf1Mbit => 0,
+        DataRate::Nrf2Mbit => 1,
+        #[cfg(feature = "51")]
+        DataRate::Nrf250Kbit => 2,
+        DataRate::Ble1Mbit => 3,
+        #[cfg(not(feature = "51"))]
+        DataRate::Ble2Mbit => 4,
+    }
+}
+
+fn data_rate_from_bits(bits: u8) -> Option<DataRate> {
+    Some(match bits {
+        0 => DataRate::Nrf1Mbit,
+        1 => DataRate::Nrf2Mbit,
+        #[cfg(feature = "51")]
+        2 => DataRate::Nrf250Kbit,
+        3 => DataRate::Ble1Mbit,
+        #[cfg(not(feature = "51"))]
+        4 => DataRate::Ble2Mbit,
+        _ => return None,
+    })
+}
+
+fn address_width_to_bits(address_width: AddressWidth) -> u8 {
+    address_width.base_len() + 1
+}
+
+fn address_width_from_bits(bits: u8) -> Option<AddressWidth> {
+    Some(match bits {
+        3 => AddressWidth::ThreeBytes,
+        4 => AddressWidth::FourBytes,
+        5 => AddressWidth::FiveBytes,
+        _ => return None,
+    })
+}
+
+// The tx power is encoded in signed dBm, the value of the TXPOWER register differs between devices
+fn tx_power_to_dbm(tx_power: TxPower) -> i8 {
+    match tx_power {
+        #[cfg(any(feature = "52833", feature = "52840"))]
+        TxPower::POS8DBM => 8,
+        #[cfg(any(feature = "52833", feature = "52840"))]
+        TxPower::POS7DBM => 7,
+        #[cfg(any(feature = "52833", feature = "52840"))]
+        TxPower::POS6DBM => 6,
+        #[cfg(any(feature = "52833", feature = "52840"))]
+        TxPower::POS5DBM => 5,
+        TxPower::POS4DBM => 4,
+        #[cfg(not(feature = "51"))]
+        TxPower::POS3DBM => 3,
+        #[cfg(any(feature = "52833", feature = "52840"))]
+        TxPower::POS2DBM => 2,
+        TxPower::_0DBM => 0,
+        TxPower::NEG4DBM => -4,
+        TxPower::NEG8DBM => -8,
+        TxPower::NEG12DBM => -12,
+        TxPower::NEG16DBM => -16,
+        TxPower::NEG20DBM => -20,
+        #[cfg(not(feature = "52832"))]
+        TxPower::NEG30DBM => -30,
+        #[cfg(not(feature = "51"))]
+        TxPower::NEG40DBM => -40,
+    }
+}
+
+fn tx_power_from_dbm(dbm: i8) -> Option<TxPower> {
+    Some(match dbm {
+        #[cfg(any(feature = "52833", feature = "52840"))]
+        8 => TxPower::POS8DBM,
+        #[cfg(any(feature = "52833", feature = "52840"))]
+        7 => TxPower::POS7DBM,
+        #[cfg(any(feature = "52833", feature = "52840"))]
+        6 => TxPower::POS6DBM,
+        #[cfg(any(feature = "52833", feature = "52840"))]
+        5 => TxPower::POS5DBM,
+        4 => TxPower::POS4DBM,
+        #[cfg(not(feature = "51"))]
+        3 => TxPower::POS3DBM,
+        #[cfg(any(feature = "52833", feature = "52840"))]
+        2 => TxPower::POS2DBM,
+        0 => TxPower::_0DBM,
+        -4 => TxPower::NEG4DBM,
+        -8 => TxPower::NEG8DBM,
+        -12 => TxPower::NEG12DBM,
+        -16 => TxPower::NEG16DBM,
+        -20 => TxPower::NEG20DBM,
+        #[cfg(not(feature = "52832"))]
+        -30 => TxPower::NEG30DBM,
+        #[cfg(not(feature = "51"))]
+        -40 => TxPower::NEG40DBM,
+        _ => return None,
+    })
+}
+
+#[cfg(feature = "serde")]
+mod serde_impls {
+    use super::*;
+    use core::convert::TryFrom;
+    use serde::{Deserialize, Serialize};
+
+    // Field by field representation of `Config`, the tx power is in dBm
+    #[derive(Serialize, Deserialize)]
+    #[serde(rename = "Config")]
+    pub(crate) struct ConfigRepr {
+        wait_for_ack_timeout: u16,
+        retransmit_delay: u16,
+        maximum_transmit_attempts: u8,
+        enabled_pipes: u8,
+        tx_power: i8,
+        maximum_payload_size: u8,
+        data_rate: DataRate,
+        crc: Crc,
+        payload_length: PayloadLength,
+        protocol: Protocol,
+        pipes: [PipeConfig; 8],
+        ramp_up: RampUp,
+        peer_ramp_up: RampUp,
+        backoff: Option<Backoff>,
+        cca: Option<Cca>,
+    }
+
+    impl From<Config> for ConfigRepr {
+        fn from(config: Config) -> Self {
+            Self {
+                wait_for_ack_timeout: config.wait_for_ack_timeout,
+                retransmit_delay: config.retransmit_delay,
+                maximum_transmit_attempts: config.maximum_transmit_attempts,
+                enabled_pipes: config.enabled_pipes,
+                tx_power: tx_power_to_dbm(config.tx_power),
+                maximum_payload_size: config.maximum_payload_size,
+                data_rate: config.data_rate,
+                crc: config.crc,
+                payload_length: config.payload_length,
+                protocol: config.protocol,
+                pipes: config.pipes,
+                ramp_up: config.ramp_up,
+                peer_ramp_up: config.peer_ramp_up,
+                backoff: config.backoff,
+                cca: config.cca,
+            }
+        }
+    }
+
+    impl TryFrom<ConfigRepr> for Config {
+        type Error = Error;
+
+        fn try_from(repr: ConfigRepr) -> Result<Self, Error> {
+            ConfigBuilder::default()
+                .wait_for_ack_timeout(repr.wait_for_ack_timeout)
+                .retransmit_delay(repr.retransmit_delay)
+                .maximum_transmit_attempts(repr.maximum_transmit_attempts)
+                .enabled_pipes(repr.enabled_pipes)
+                .tx_power(tx_power_from_dbm(repr.tx_power).ok_or(Error::InvalidParameters)?)
+                .max_payload_size(repr.maximum_payload_size)
+                .data_rate(repr.data_rate)
+                .crc(repr.crc)
+                .payload_length(repr.payload_length)
+                .protocol(repr.protocol)
+                .pipes(repr.pipes)
+                .ramp_up(repr.ramp_up)
+                .peer_ramp_up(repr.peer_ramp_up)
+                .backoff(repr.backoff)
+                .cca(repr.cca)
+                .check()
+        }
+    }
+
+    // Field by field representation of `Addresses`
+    #[derive(Serialize, Deserialize)]
+    #[serde(rename = "Addresses")]
+    pub(crate) struct AddressesRepr {
+        address_width: AddressWidth,
+        base0: [u8; 4],
+        base1: [u8; 4],
+        prefixes0: [u8; 4],
+        prefixes1: [u8; 4],
+        rf_channel: u8,
+    }
+
+    impl From<Addresses> for AddressesRepr {
+        fn from(addresses: Addresses) -> Self {
+            Self {
+                address_width: addresses.address_width,
+                base0: addresses.base0,
+                base1: addresses.base1,
+                prefixes0: addresses.prefixes0,
+                prefixes1: addresses.prefixes1,
+                rf_channel: addresses.rf_channel,
+            }
+        }
+    }
+
+    impl TryFrom<AddressesRepr> for Addresses {
+        type Error = Error;
+
+        fn try_from(repr: AddressesRepr) -> Result<Self, Error> {
+            let base_len = usize::from(repr.address_width.base_len());
+            Addresses::new_with_width(
+                repr.address_width,
+                &repr.base0[..base_len],
+                &repr.base1[..base_len],
+                repr.prefixes0,
+                repr.prefixes1,
+                repr.rf_channel,
+            )
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+pub(crate) use serde_impls::{AddressesRepr, ConfigRepr};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn full_config() -> Config {
+        let mut pipes = [PipeConfig::default(); 8];
+        pipes[2] = PipeConfig::new(false, None);
+        pipes[5] = PipeConfig::new(true, Some(32));
+        ConfigBuilder::default()
+            .wait_for_ack_timeout(300)
+            .retransmit_delay(600)
+            .maximum_transmit_attempts(7)
+            .enabled_pipes(0b0010_0101)
+            .tx_power(TxPower::NEG8DBM)
+            .max_payload_size(32)
+            .crc(Crc::OneByte)
+            .payload_length(PayloadLength::Static(32))
+            .pipes(pipes)
+            .peer_ramp_up(RampUp::Fast)
+            .backoff(Some(Backoff::new(150, true, 4_000)))
+            .cca(Some(Cca::new(70, 4, 500, 10)))
+            .check()
+            .unwrap()
+    }
+
+    #[test]
+    fn config_round_trip() {
+        let bytes = full_config().to_bytes();
+        let decoded = Config::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.to_bytes(), bytes);
+        assert_eq!(decoded.payload_length(), PayloadLength::Static(32));
+        assert_eq!(decoded.pipes()[5].maximum_payload_size(), Some(32));
+        assert!(!decoded.pipes()[2].auto_ack());
+        assert_eq!(decoded.tx_power(), TxPower::NEG8DBM);
+        assert_eq!(decoded.peer_ramp_up(), RampUp::Fast);
+        assert_eq!(decoded.backoff(), Some(Backoff::new(150, true, 4_000)));
+        assert_eq!(decoded.cca(), Some(Cca::new(70, 4, 500, 10)));
+    }
+
+    #[test]
+    fn tx_power_is_encoded_in_dbm() {
+        assert_eq!(full_config().to_bytes()[7], -8i8 as u8);
+        for dbm in i8::MIN..=i8::MAX {
+            if let Some(tx_power) = tx_power_from_dbm(dbm) {
+                assert_eq!(tx_power_to_dbm(tx_power), dbm);
+            }
+        }
+        assert_eq!(tx_power_from_dbm(4), Some(TxPower::POS4DBM));
+        assert_eq!(tx_power_from_dbm(-20), Some(TxPower::NEG20DBM));
+        assert_eq!(tx_power_from_dbm(1), None);
+        assert_eq!(tx_power_from_dbm(-128), None);
+    }
+
+    #[test]
+    fn config_rejects_bad_encodings() {
+        let bytes = full_config().to_bytes();
+        assert!(Config::from_bytes(&[]).is_err());
+        assert!(Config::from_bytes(&bytes[..Config::ENCODED_SIZE - 1]).is_err());
+
+        let mut bad = bytes;
+        bad[0] = CONFIG_VERSION + 1;
+        assert!(Config::from_bytes(&bad).is_err());
+
+        let mut bad = bytes;
+        bad[7] = 1;
+        assert!(Config::from_bytes(&bad).is_err());
+
+        let mut bad = bytes;
+        bad[10] = 3;
+        assert!(Config::from_bytes(&bad).is_err());
+
+        let mut bad = bytes;
+        bad[PIPES_OFFSET] |= 1 << 7;
+        assert!(Config::from_bytes(&bad).is_err());
+
+        let mut bad = bytes;
+        bad[RAMP_UP_OFFSET] |= 1 << 2;
+        assert!(Config::from_bytes(&bad).is_err());
+
+        let mut bad = bytes;
+        bad[BACKOFF_OFFSET] = BACKOFF_EXPONENTIAL;
+        assert!(Config::from_bytes(&bad).is_err());
+
+        let mut bad = bytes;
+        bad[CCA_OFFSET] = 2;
+        assert!(Config::from_bytes(&bad).is_err());
+
+        let mut bad = bytes;
+        bad[CCA_OFFSET + 2] = 0;
+        assert!(Config::from_bytes(&bad).is_err());
+
+        // Decoding goes through the validation of the builder
+        let mut bad = bytes;
+        bad[12] = 253;
+        assert!(Config::from_bytes(&bad).is_err());
+    }
+
+    #[test]
+    fn addresses_round_trip() {
+        let addresses = Addresses::new_with_width(
+            AddressWidth::ThreeBytes,
+            &[0x12, 0x34],
+            &[0x56, 0x78],
+            [0xE7, 0xC2, 0xC3, 0xC4],
+            [0xC5, 0xC6, 0xC7, 0xC8],
+            80,
+        )
+        .unwrap();
+        let bytes = addresses.to_bytes();
+        let decoded = Addresses::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.to_bytes(), bytes);
+        assert_eq!(decoded.base0(), [0x12, 0x34, 0, 0]);
+        assert_eq!(decoded.rf_channel(), 80);
+
+        let mut bad = bytes;
+        bad[1] = 6;
+        assert!(Addresses::from_bytes(&bad).is_err());
+        assert!(Addresses::from_bytes(&bytes[..18]).is_err());
+    }
+}
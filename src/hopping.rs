@@ -0,0 +1,220 @@
+//! Frequency hopping
+//!
+//! Both ends share a [`HopTable`](struct.HopTable.html) with the same channel sequence. The PRX
+//! listens on each channel for `dwell_time` microseconds and moves to the next one when no valid
+//! packet is received in that period, it stays on the current channel while packets keep coming.
+//! The PTX stays on its channel while its packets are acknowledged, after `resync_threshold`
+//! consecutive packets reach the maximum number of attempts, it moves to the next channel of the
+//! table.
+//!
+//! For the PTX to find the PRX, the retransmissions of a packet should span a full PRX cycle,
+//! i.e. `maximum_transmit_attempts * retransmit_delay` should be greater than
+//! `dwell_time * channels`.
+
+use crate::Error;
+
+/// Maximum number of channels in a hop table
+pub const MAX_HOP_CHANNELS: usize = 16;
+
+/// Sequence of channels shared by the PTX and the PRX
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct HopTable {
+    channels: [u8; MAX_HOP_CHANNELS],
+    len: u8,
+}
+
+impl HopTable {
+    /// Creates a new hop table
+    ///
+    /// # Errors
+    ///
+    /// An error will be returned if `channels` is empty or longer than
+    /// [`MAX_HOP_CHANNELS`](constant.MAX_HOP_CHANNELS.html), or if any of its channels is
+    /// greater than 100.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use esb::hopping::HopTable;
+    ///
+    /// let table = HopTable::new(&[4, 25, 42, 63, 77]).unwrap();
+    /// assert_eq!(table.channels(), &[4, 25, 42, 63, 77]);
+    ///
+    /// assert!(HopTable::new(&[]).is_err());
+    /// assert!(HopTable::new(&[4, 101]).is_err());
+    /// ```
+    pub fn new(channels: &[u8]) -> Result<Self, Error> {
+        if channels.is_empty()
+            || channels.len() > MAX_HOP_CHANNELS
+            || channels.iter().any(|&channel| channel > 100)
+        {
+            return Err(Error::InvalidParameters);
+        }
+        let mut table = [0; MAX_HOP_CHANNELS];
+        table[..channels.len()].copy_from_slice(channels);
+        Ok(Self {
+            channels: table,
+            len: channels.len() as u8,
+        })
+    }
+
+    /// Channels of the table, in hop order
+    pub fn channels(&self) -> &[u8] {
+        &self.channels[..usize::from(self.len)]
+    }
+}
+
+/// Frequency hopping settings
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Hopping {
+    table: HopTable,
+    dwell_time: u16,
+    resync_threshold: u8,
+}
+
+impl Hopping {
+    /// Creates new frequency hopping settings
+    ///
+    /// * `table` - Channel sequence shared by both ends.
+    /// * `dwell_time` - Microseconds the PRX waits for a valid packet before hopping.
+    /// * `resync_threshold` - Consecutive failed packets before the PTX hops.
+    ///
+    /// # Errors
+    ///
+    /// An error will be returned if `dwell_time` or `resync_threshold` are zero.
+    pub fn new(table: HopTable, dwell_time: u16, resync_threshold: u8) -> Result<Self, Error> {
+        if dwell_time == 0 || resync_threshold == 0 {
+            return Err(Error::InvalidParameters);
+        }
+        Ok(Self {
+            table,
+            dwell_time,
+            resync_threshold,
+        })
+    }
+
+    /// Channel sequence shared by both ends
+    pub fn table(&self) -> &HopTable {
+        &self.table
+    }
+
+    /// Microseconds the PRX waits for a valid packet before hopping
+    pub fn dwell_time(&self) -> u16 {
+        self.dwell_time
+    }
+
+    /// Consecutive packets that must reach the maximum number of attempts before the PTX hops
+    pub fn resync_threshold(&self) -> u8 {
+        self.resync_threshold
+    }
+}
+
+/// Hopping state kept by [EsbIrq](../irq/struct.EsbIrq.html)
+pub(crate) struct Hopper {
+    pub(crate) hopping: Hopping,
+    index: u8,
+    failures: u8,
+}
+
+impl Hopper {
+    pub(crate) fn new(hopping: Hopping) -> Self {
+        Self {
+            hopping,
+            index: 0,
+            failures: 0,
+        }
+    }
+
+    /// Channel of the current position in the table
+    pub(crate) fn channel(&self) -> u8 {
+        self.hopping.table.channels[usize::from(self.index)]
+    }
+
+    /// Moves to the next channel of the table and returns it
+    pub(crate) fn hop(&mut self) -> u8 {
+        self.index += 1;
+        if self.index == self.hopping.table.len {
+            self.index = 0;
+        }
+        self.failures = 0;
+        self.channel()
+    }
+
+    /// Registers a packet that reached the maximum number of attempts, returns the new channel if
+    /// it is time to hop
+    pub(crate) fn tx_failed(&mut self) -> Option<u8> {
+        self.failures += 1;
+        if self.failures >= self.hopping.resync_threshold {
+            Some(self.hop())
+        } else {
+            None
+        }
+    }
+
+    /// Registers an acknowledged packet
+    pub(crate) fn tx_succeeded(&mut self) {
+        self.failures = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hopper(resync_threshold: u8) -> Hopper {
+        let table = HopTable::new(&[4, 25, 42]).unwrap();
+        Hopper::new(Hopping::new(table, 1_000, resync_threshold).unwrap())
+    }
+
+    #[test]
+    fn hop_table_limits() {
+        let channels = [0; MAX_HOP_CHANNELS + 1];
+        assert!(HopTable::new(&channels[..MAX_HOP_CHANNELS]).is_ok());
+        assert!(HopTable::new(&channels).is_err());
+        assert!(HopTable::new(&[100]).is_ok());
+    }
+
+    #[test]
+    fn hop_wraps_around() {
+        let mut hopper = hopper(1);
+        assert_eq!(hopper.channel(), 4);
+        assert_eq!(hopper.hop(), 25);
+        assert_eq!(hopper.hop(), 42);
+        assert_eq!(hopper.hop(), 4);
+        assert_eq!(hopper.channel(), 4);
+    }
+
+    #[test]
+    fn single_channel_table() {
+        let table = HopTable::new(&[7]).unwrap();
+        let mut hopper = Hopper::new(Hopping::new(table, 1_000, 1).unwrap());
+        assert_eq!(hopper.hop(), 7);
+        assert_eq!(hopper.tx_failed(), Some(7));
+    }
+
+    #[test]
+    fn hops_after_resync_threshold() {
+        let mut hopper = hopper(3);
+        assert_eq!(hopper.tx_failed(), None);
+        assert_eq!(hopper.tx_failed(), None);
+        assert_eq!(hopper.tx_failed(), Some(25));
+        // The failures are counted again after a hop
+        assert_eq!(hopper.tx_failed(), None);
+    }
+
+    #[test]
+    fn success_resets_failures() {
+        let mut hopper = hopper(2);
+        assert_eq!(hopper.tx_failed(), None);
+        hopper.tx_succeeded();
+        assert_eq!(hopper.tx_failed(), None);
+        assert_eq!(hopper.tx_failed(), Some(25));
+    }
+
+    #[test]
+    fn hopping_rejects_zero_settings() {
+        let table = HopTable::new(&[4]).unwrap();
+        assert!(Hopping::new(table, 0, 1).is_err());
+        assert!(Hopping::new(table, 1_000, 0).is_err());
+    }
+}
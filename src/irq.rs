@@ -1,16 +1,24 @@
 use crate::{
-    app::Addresses,
-    payload::{EsbHeader, PayloadR, PayloadW},
+    app::{unpack_channel_request, Addresses},
+    cancel::TxCancel,
+    hopping::{Hopper, Hopping},
+    payload::{EsbHeader, PayloadR, PayloadW, TxPayload},
     peripherals::{EsbRadio, EsbTimer, Interrupt, RxPayloadState, NVIC},
-    Config, Error, RAMP_UP_TIME,
+    polling::{Poller, Polling},
+    random::{RandomSource, XorShift32},
+    report::{ProbeResult, TxOutcome, TxReport, TxReports},
+    stats::EsbStats,
+    Config, Error, PayloadLength, Protocol,
 };
 use bbqueue::{
+    consts::U0,
     framed::{FrameConsumer, FrameProducer},
     ArrayLength,
 };
 use core::{
     marker::PhantomData,
-    sync::atomic::{AtomicBool, Ordering},
+    ops::RangeInclusive,
+    sync::atomic::{AtomicBool, AtomicU16, Ordering},
 };
 
 /// Type to represent the driver in the disabled mode
@@ -28,6 +36,12 @@ pub enum StatePTX {
     TransmitterWaitAck,
     /// ESB on PTX state waiting for the retransmit timeout.
     TransmitterWaitRetransmit,
+    /// ESB on PTX state assessing the channel before a transmission.
+    TransmitterAssessChannel,
+    /// ESB on PTX state waiting to assess a busy channel again.
+    TransmitterWaitChannel,
+    /// ESB on PTX state waiting for the polling interval with an empty outgoing queue.
+    TransmitterWaitPoll,
 }
 
 /// The current state of the radio when in PRX mode
@@ -43,6 +57,54 @@ pub enum StatePRX {
     TransmittingRepeatedAck,
 }
 
+/// Background noise measured on a channel by
+/// [scan_channels](struct.EsbIrq.html#method.scan_channels)
+///
+/// The RSSI values are the magnitude of the received signal strength in dBm, i.e. a value of 90
+/// means -90 dBm, higher values mean a quieter channel.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ChannelNoise {
+    /// Channel where the samples were taken
+    pub channel: u8,
+    /// Lowest RSSI magnitude, i.e. the strongest signal
+    pub minimum: u8,
+    /// Highest RSSI magnitude, i.e. the weakest signal
+    pub maximum: u8,
+    /// Average of the RSSI magnitudes
+    pub average: u8,
+}
+
+impl ChannelNoise {
+    // Statistics of `samples` RSSI samples, from their minimum, maximum and sum
+    fn new(channel: u8, minimum: u8, maximum: u8, sum: u32, samples: u16) -> Self {
+        Self {
+            channel,
+            minimum,
+            maximum,
+            average: (sum / u32::from(samples)) as u8,
+        }
+    }
+}
+
+// Whether the deadline is before `now`, with a wrapping comparison: the deadline passed if it is
+// in the past half of the clock
+fn deadline_passed(now: u32, deadline: u32) -> bool {
+    (now.wrapping_sub(deadline) as i32) > 0
+}
+
+// Checks the arguments of a channel scan and returns the number of channels to scan
+fn scan_count(channels: &RangeInclusive<u8>, samples: u16, results: usize) -> Result<usize, Error> {
+    let (first, last) = (*channels.start(), *channels.end());
+    if first > last || last > 100 || samples == 0 {
+        return Err(Error::InvalidParameters);
+    }
+    let count = usize::from(last - first) + 1;
+    if results < count {
+        return Err(Error::InvalidParameters);
+    }
+    Ok(count)
+}
+
 pub struct IrqTimer<T: EsbTimer> {
     /// Flag to determine if the timer caused the interrupt
     pub(crate) timer_flag: &'static AtomicBool,
@@ -73,10 +135,11 @@ impl<T: EsbTimer> IrqTimer<T> {
 /// It is intended to be used inside of the `RADIO` interrupt,
 /// and allows for sending or receiving frames from the Application
 /// hardware.
-pub struct EsbIrq<OutgoingLen, IncomingLen, Timer, STATE>
+pub struct EsbIrq<OutgoingLen, IncomingLen, Timer, STATE, PriorityLen = U0>
 where
     OutgoingLen: ArrayLength<u8>,
     IncomingLen: ArrayLength<u8>,
+    PriorityLen: ArrayLength<u8>,
     Timer: EsbTimer,
 {
     /// Producer to send incoming frames FROM the radio, TO the application
@@ -85,11 +148,17 @@ where
     /// Consumer to receive outgoing frames TO the radio, FROM the application
     pub(crate) cons_from_app: FrameConsumer<'static, OutgoingLen>,
 
+    /// Consumer to receive urgent outgoing frames TO the radio, FROM the application
+    pub(crate) cons_priority_from_app: FrameConsumer<'static, PriorityLen>,
+
+    /// Whether the packet being sent by the PTX comes from the priority queue
+    pub(crate) tx_priority: bool,
+
     /// Peripheral timer, use for ACK and other timeouts
     pub(crate) timer: Timer,
 
     /// Wrapping structure of the nRF RADIO peripheral
-    pub(crate) radio: EsbRadio<OutgoingLen, IncomingLen>,
+    pub(crate) radio: EsbRadio<OutgoingLen, IncomingLen, PriorityLen>,
 
     /// Current state of the Radio/IRQ task
     pub(crate) state: STATE,
@@ -100,11 +169,48 @@ where
     /// The number of attempts to send the current packet
     pub(crate) attempts: u8,
 
+    /// The number of busy clear channel assessments in a row for the current attempt
+    pub(crate) busy_assessments: u8,
+
     /// Flag to determine if the timer caused the interrupt
     pub(crate) timer_flag: &'static AtomicBool,
 
     /// Protocol configuration
     pub(crate) config: Config,
+
+    /// Channel change waiting for a safe point to be applied
+    pub(crate) pending_channel: Option<u8>,
+
+    /// Channel change requests from the application, the high byte is a sequence number and the
+    /// low byte is the channel
+    pub(crate) channel_request: &'static AtomicU16,
+
+    /// Sequence number of the last channel request taken from the application
+    pub(crate) channel_request_seq: u8,
+
+    /// Frequency hopping state, if enabled
+    pub(crate) hopper: Option<Hopper>,
+
+    /// Acknowledgement payload polling state, if enabled in PTX mode
+    pub(crate) poller: Option<Poller>,
+
+    /// Reports of tagged packets, TO the application
+    pub(crate) tx_reports: &'static TxReports,
+
+    /// Generator of the retransmit backoff
+    pub(crate) backoff_rng: XorShift32,
+
+    /// User clock used for the packet deadlines
+    pub(crate) clock: Option<fn() -> u32>,
+
+    /// Cancellations of outgoing packets, FROM the application
+    pub(crate) tx_cancel: &'static TxCancel,
+
+    /// Time measured so far for the link probe being sent, in micro seconds
+    pub(crate) probe_time: u32,
+
+    /// Link statistics, TO the application
+    pub(crate) stats: &'static EsbStats,
 }
 
 struct Events {
@@ -112,33 +218,113 @@ struct Events {
     timer: bool,
 }
 
-impl<OutgoingLen, IncomingLen, Timer, STATE> EsbIrq<OutgoingLen, IncomingLen, Timer, STATE>
+impl<OutgoingLen, IncomingLen, Timer, STATE, PriorityLen>
+    EsbIrq<OutgoingLen, IncomingLen, Timer, STATE, PriorityLen>
 where
     OutgoingLen: ArrayLength<u8>,
     IncomingLen: ArrayLength<u8>,
+    PriorityLen: ArrayLength<u8>,
     Timer: EsbTimer,
 {
     /// Puts the driver in the disabled state
-    pub fn into_disabled(mut self) -> EsbIrq<OutgoingLen, IncomingLen, Timer, Disabled> {
+    pub fn into_disabled(
+        mut self,
+    ) -> EsbIrq<OutgoingLen, IncomingLen, Timer, Disabled, PriorityLen> {
         // Put the radio in a known state
         self.radio.stop(true);
         Timer::clear_interrupt_retransmit();
         Timer::clear_interrupt_ack();
         let _ = self.check_and_clear_flags();
+        self.tx_cancel.set_ptx(false);
 
         EsbIrq {
             prod_to_app: self.prod_to_app,
             cons_from_app: self.cons_from_app,
+            cons_priority_from_app: self.cons_priority_from_app,
+            tx_priority: false,
             timer: self.timer,
             radio: self.radio,
             state: Disabled,
             addresses: self.addresses,
             attempts: 0,
+            busy_assessments: 0,
             timer_flag: self.timer_flag,
             config: self.config,
+            pending_channel: self.pending_channel,
+            channel_request: self.channel_request,
+            channel_request_seq: self.channel_request_seq,
+            hopper: self.hopper,
+            poller: None,
+            tx_reports: self.tx_reports,
+            backoff_rng: self.backoff_rng,
+            clock: self.clock,
+            tx_cancel: self.tx_cancel,
+            probe_time: 0,
+            stats: self.stats,
         }
     }
 
+    /// Returns the active protocol configuration
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Returns the active addresses, the RF channel reflects any channel change already applied
+    pub fn addresses(&self) -> &Addresses {
+        &self.addresses
+    }
+
+    /// Returns the frequency hopping settings, if hopping is enabled
+    pub fn hopping(&self) -> Option<&Hopping> {
+        self.hopper.as_ref().map(|hopper| &hopper.hopping)
+    }
+
+    /// Seeds the generator of the retransmit backoff, see [Backoff](../struct.Backoff.html)
+    ///
+    /// Each device should use a different seed, e.g. from the `RNG` peripheral, otherwise devices
+    /// that collide will draw the same delays. The source is only read here.
+    pub fn seed_backoff<R: RandomSource>(&mut self, source: &mut R) {
+        self.backoff_rng = XorShift32::new(source.next_u32());
+    }
+
+    /// Sets the clock used for the packet deadlines, see
+    /// [`EsbHeaderBuilder::deadline`](../payload/struct.EsbHeaderBuilder.html#method.deadline)
+    ///
+    /// The clock must return the current time in ticks of the user's choice and is called from
+    /// the radio interrupt before each transmission, so it must be fast. `None` disables the
+    /// deadlines.
+    pub fn set_clock(&mut self, clock: Option<fn() -> u32>) {
+        self.clock = clock;
+    }
+
+    // Checks that the new configuration is compatible with the application side, stops the radio
+    // and reprograms it, the radio is left disabled
+    fn reprogram(&mut self, config: Config, addresses: Addresses) -> Result<(), Error> {
+        // These settings are copied into `EsbApp` and can't change without splitting again
+        if config.maximum_payload_size != self.config.maximum_payload_size
+            || config.pipe_maximum_payload_sizes() != self.config.pipe_maximum_payload_sizes()
+            || config.payload_length != self.config.payload_length
+            || config.protocol != self.config.protocol
+        {
+            return Err(Error::InvalidParameters);
+        }
+
+        // Put the radio in a known state
+        self.radio.stop(true);
+        Timer::clear_interrupt_retransmit();
+        Timer::clear_interrupt_ack();
+        let _ = self.check_and_clear_flags();
+
+        self.config = config;
+        self.addresses = addresses;
+        // The new addresses define the channel
+        self.pending_channel = None;
+        self.attempts = 0;
+        self.busy_assessments = 0;
+        self.radio.init(&self.config, &self.addresses);
+        Ok(())
+    }
+
     fn check_and_clear_flags(&mut self) -> Events {
         let evts = Events {
             disabled: self.radio.check_disabled_event(),
@@ -157,61 +343,275 @@ where
 
         evts
     }
+
+    // Takes the last channel change requested by the application, if any
+    fn take_channel_request(&mut self) {
+        let (seq, channel) = unpack_channel_request(self.channel_request.load(Ordering::Acquire));
+        if seq != self.channel_request_seq {
+            self.channel_request_seq = seq;
+            self.pending_channel = Some(channel);
+        }
+    }
+
+    // Applies a pending channel change, the radio must be disabled
+    fn apply_pending_channel(&mut self) -> bool {
+        if let Some(channel) = self.pending_channel.take() {
+            self.radio.set_channel(channel);
+            self.addresses.rf_channel = channel;
+            true
+        } else {
+            false
+        }
+    }
 }
 
-impl<OutgoingLen, IncomingLen, Timer> EsbIrq<OutgoingLen, IncomingLen, Timer, Disabled>
+impl<OutgoingLen, IncomingLen, Timer, PriorityLen>
+    EsbIrq<OutgoingLen, IncomingLen, Timer, Disabled, PriorityLen>
 where
     OutgoingLen: ArrayLength<u8>,
     IncomingLen: ArrayLength<u8>,
+    PriorityLen: ArrayLength<u8>,
     Timer: EsbTimer,
 {
+    /// Changes the RF channel, it must be between 0 and 100
+    pub fn set_channel(&mut self, channel: u8) -> Result<(), Error> {
+        if channel > 100 {
+            return Err(Error::InvalidParameters);
+        }
+        self.pending_channel = Some(channel);
+        self.apply_pending_channel();
+        Ok(())
+    }
+
+    /// Applies a new configuration and addresses
+    ///
+    /// The maximum payload sizes, the payload length mode and the protocol mode can't be changed
+    /// at runtime, `Error::InvalidParameters` is returned if they differ from the active ones.
+    pub fn reconfigure(&mut self, config: Config, addresses: Addresses) -> Result<(), Error> {
+        self.reprogram(config, addresses)
+    }
+
+    /// Measures the background noise of a range of channels
+    ///
+    /// Each channel of `channels` is sampled `samples` times and its statistics are written to
+    /// `results`, in order. The radio doesn't receive packets during the scan and returns to the
+    /// active channel at the end. Returns the number of channels scanned.
+    ///
+    /// # Errors
+    ///
+    /// An error will be returned if `channels` is empty or contains channels greater than 100,
+    /// if `samples` is zero, or if `results` can't hold all the channels of the range.
+    pub fn scan_channels(
+        &mut self,
+        channels: RangeInclusive<u8>,
+        samples: u16,
+        results: &mut [ChannelNoise],
+    ) -> Result<usize, Error> {
+        let count = scan_count(&channels, samples, results.len())?;
+        for (channel, result) in channels.zip(results.iter_mut()) {
+            self.radio.set_channel(channel);
+            let (minimum, maximum, sum) = self.radio.sample_rssi(samples);
+            *result = ChannelNoise::new(channel, minimum, maximum, sum, samples);
+        }
+        self.radio.set_channel(self.addresses.rf_channel);
+        Ok(count)
+    }
+
+    /// Enables frequency hopping, the radio moves to the first channel of the table
+    ///
+    /// While hopping is enabled, a channel set through `set_channel` or `EsbApp::request_channel`
+    /// is only used until the next hop. Refer to the [hopping](../hopping/index.html) module for
+    /// more information.
+    pub fn enable_hopping(&mut self, hopping: Hopping) {
+        let hopper = Hopper::new(hopping);
+        self.pending_channel = Some(hopper.channel());
+        self.apply_pending_channel();
+        self.hopper = Some(hopper);
+    }
+
+    /// Disables frequency hopping, the radio stays on the current channel
+    pub fn disable_hopping(&mut self) {
+        self.hopper = None;
+    }
+
     /// Puts the driver in the PTX mode
-    pub fn into_ptx(self) -> EsbIrq<OutgoingLen, IncomingLen, Timer, StatePTX> {
+    pub fn into_ptx(self) -> EsbIrq<OutgoingLen, IncomingLen, Timer, StatePTX, PriorityLen> {
+        self.tx_cancel.set_ptx(true);
         EsbIrq {
             prod_to_app: self.prod_to_app,
             cons_from_app: self.cons_from_app,
+            cons_priority_from_app: self.cons_priority_from_app,
+            tx_priority: false,
             timer: self.timer,
             radio: self.radio,
             state: StatePTX::IdleTx,
             addresses: self.addresses,
             attempts: 0,
+            busy_assessments: 0,
             timer_flag: self.timer_flag,
             config: self.config,
+            pending_channel: self.pending_channel,
+            channel_request: self.channel_request,
+            channel_request_seq: self.channel_request_seq,
+            hopper: self.hopper,
+            poller: None,
+            tx_reports: self.tx_reports,
+            backoff_rng: self.backoff_rng,
+            clock: self.clock,
+            tx_cancel: self.tx_cancel,
+            probe_time: 0,
+            stats: self.stats,
         }
     }
 
     /// Puts the driver in the PRX mode in a idle state, the user must call
     /// [start_receiving](struct.EsbIrq.html#method.start_receiving) to enable the radio for receiving
-    pub fn into_prx(self) -> EsbIrq<OutgoingLen, IncomingLen, Timer, StatePRX> {
+    pub fn into_prx(self) -> EsbIrq<OutgoingLen, IncomingLen, Timer, StatePRX, PriorityLen> {
+        self.tx_cancel.set_ptx(false);
         EsbIrq {
             prod_to_app: self.prod_to_app,
             cons_from_app: self.cons_from_app,
+            cons_priority_from_app: self.cons_priority_from_app,
+            tx_priority: false,
             timer: self.timer,
             radio: self.radio,
             state: StatePRX::IdleRx,
             addresses: self.addresses,
             attempts: 0,
+            busy_assessments: 0,
             timer_flag: self.timer_flag,
             config: self.config,
+            pending_channel: self.pending_channel,
+            channel_request: self.channel_request,
+            channel_request_seq: self.channel_request_seq,
+            hopper: self.hopper,
+            poller: None,
+            tx_reports: self.tx_reports,
+            backoff_rng: self.backoff_rng,
+            clock: self.clock,
+            tx_cancel: self.tx_cancel,
+            probe_time: 0,
+            stats: self.stats,
         }
     }
 }
 
-impl<OutgoingLen, IncomingLen, Timer> EsbIrq<OutgoingLen, IncomingLen, Timer, StatePTX>
+impl<OutgoingLen, IncomingLen, Timer, PriorityLen>
+    EsbIrq<OutgoingLen, IncomingLen, Timer, StatePTX, PriorityLen>
 where
     OutgoingLen: ArrayLength<u8>,
     IncomingLen: ArrayLength<u8>,
+    PriorityLen: ArrayLength<u8>,
     Timer: EsbTimer,
 {
+    /// Changes the RF channel, it must be between 0 and 100
+    ///
+    /// The change is applied immediately if the driver is idle, otherwise it is deferred until the
+    /// current packet is sent or dropped, retransmissions of a packet always use the same channel.
+    pub fn set_channel(&mut self, channel: u8) -> Result<(), Error> {
+        if channel > 100 {
+            return Err(Error::InvalidParameters);
+        }
+        self.pending_channel = Some(channel);
+        if self.state == StatePTX::IdleTx {
+            self.apply_pending_channel();
+        }
+        Ok(())
+    }
+
+    /// Applies a new configuration and addresses
+    ///
+    /// The maximum payload sizes, the payload length mode and the protocol mode can't be changed
+    /// at runtime, `Error::InvalidParameters` is returned if they differ from the active ones.
+    ///
+    /// The radio is stopped and reprogrammed, if a packet was being sent, it is sent again with
+    /// the new configuration. The attempts made so far count towards its maximum number of
+    /// attempts, including a transmission aborted by the reconfiguration. A poll being sent is
+    /// abandoned.
+    pub fn reconfigure(&mut self, config: Config, addresses: Addresses) -> Result<(), Error> {
+        let was_idle = self.state == StatePTX::IdleTx;
+        let transmitting = match self.state {
+            StatePTX::TransmitterTx
+            | StatePTX::TransmitterTxNoAck
+            | StatePTX::TransmitterWaitAck => !self.poll_in_flight(),
+            _ => false,
+        };
+        let attempts = self.attempts.saturating_add(u8::from(transmitting));
+        self.reprogram(config, addresses)?;
+        self.attempts = attempts;
+        if let Some(poller) = self.poller.as_mut() {
+            poller.in_flight = false;
+        }
+        self.state = StatePTX::IdleTx;
+        if !was_idle {
+            // The packet wasn't released, so it will be read again
+            self.send_packet();
+        }
+        Ok(())
+    }
+
+    /// Enables acknowledgement payload polling, refer to the [polling](../polling/index.html)
+    /// module for more information
+    ///
+    /// The first poll is sent after the polling interval if the outgoing queue is empty.
+    ///
+    /// # Errors
+    ///
+    /// An error will be returned if the protocol mode is not Enhanced ShockBurst, if the payload
+    /// length is static, or if the acknowledgements of the polling pipe are disabled, since the
+    /// PRX couldn't answer the polls with a payload.
+    pub fn enable_polling(&mut self, polling: Polling) -> Result<(), Error> {
+        if self.config.protocol != Protocol::EnhancedShockBurst
+            || self.config.payload_length != PayloadLength::Dynamic
+            || !self.config.pipes()[usize::from(polling.pipe())].auto_ack()
+        {
+            return Err(Error::InvalidParameters);
+        }
+        self.disable_polling();
+        self.poller = Some(Poller::new(polling));
+        if self.state == StatePTX::IdleTx {
+            self.send_packet();
+        }
+        Ok(())
+    }
+
+    /// Disables acknowledgement payload polling, a poll being sent is abandoned
+    pub fn disable_polling(&mut self) {
+        let in_flight = matches!(
+            self.poller.take(),
+            Some(Poller {
+                in_flight: true,
+                ..
+            })
+        );
+        if in_flight || self.state == StatePTX::TransmitterWaitPoll {
+            self.radio.stop(true);
+            Timer::clear_interrupt_retransmit();
+            Timer::clear_interrupt_ack();
+            let _ = self.check_and_clear_flags();
+            self.send_packet();
+        }
+    }
+
+    /// Returns the polling settings, if polling is enabled
+    pub fn polling(&self) -> Option<&Polling> {
+        self.poller.as_ref().map(|poller| &poller.polling)
+    }
+
     /// Must be called inside the radio interrupt handler
     pub fn radio_interrupt(&mut self) -> Result<StatePTX, Error> {
         let Events { disabled, timer } = self.check_and_clear_flags();
+        self.take_channel_request();
 
         // We only trigger the interrupt in these three events, if we didn't trigger it then the
-        // user did.
+        // user did. The events of the clear channel assessment are checked by the radio.
         let user_event = !disabled && !timer;
 
-        if user_event && self.state != StatePTX::IdleTx {
+        if user_event
+            && self.state != StatePTX::IdleTx
+            && self.state != StatePTX::TransmitterWaitPoll
+            && self.state != StatePTX::TransmitterAssessChannel
+        {
             return Ok(self.state);
         }
 
@@ -224,8 +624,11 @@ where
                 }
             }
             StatePTX::TransmitterTxNoAck => {
-                // Transmission ended
+                // Transmission ended, the attempts only go above zero if a reconfiguration aborted
+                // a previous transmission of the packet
+                self.report(self.radio.tx_header(), TxOutcome::Sent, self.attempts + 1);
                 self.radio.finish_tx_no_ack();
+                self.attempts = 0;
                 self.send_packet();
             }
             StatePTX::TransmitterTx => {
@@ -241,31 +644,103 @@ where
                 } else {
                     self.radio.stop(true);
                     self.state = StatePTX::IdleTx;
+                    self.stats.count_incoming_queue_full();
+                    if self.poll_in_flight() {
+                        // Polling goes on, the application makes room in the queue meanwhile
+                        self.poll_done(false);
+                        self.send_packet();
+                    }
                     return Err(Error::IncomingQueueFull);
                 }
 
+                // The packet can override the retransmit delay, but not below what the configured
+                // timing needs
+                let header = self.radio.tx_header();
+                let mut retransmit_delay = match header.and_then(EsbHeader::retransmit_delay) {
+                    Some(delay) => delay.max(self.config.minimum_retransmit_delay() as u16),
+                    None => self.config.retransmit_delay,
+                };
+                if let Some(backoff) = self.config.backoff() {
+                    retransmit_delay =
+                        backoff.delay(retransmit_delay, self.attempts, self.backoff_rng.next_u32());
+                }
+
+                // The timer runs since the start of the transmission of a probe, it is cleared when
+                // the retransmit timer is set
+                if header.and_then(EsbHeader::probe).is_some() {
+                    self.probe_time = Timer::now();
+                }
+
                 // The radio will be disabled if we retransmit, because of that we need to take into
                 // account the ramp-up time for TX
                 self.timer
-                    .set_interrupt_retransmit(self.config.retransmit_delay - RAMP_UP_TIME);
+                    .set_interrupt_retransmit(retransmit_delay - self.config.ramp_up().time());
 
-                // Takes into account the RX ramp-up time
-                self.timer
-                    .set_interrupt_ack(self.config.wait_for_ack_timeout + RAMP_UP_TIME);
+                // Takes into account our RX ramp-up time and the peer's TX ramp-up time
+                self.timer.set_interrupt_ack(
+                    self.config.wait_for_ack_timeout + self.config.ack_ramp_up_time(),
+                );
+            }
+            StatePTX::TransmitterWaitAck if self.poll_in_flight() => {
+                // Polls are never retransmitted, `clear_interrupt_retransmit` also resets and
+                // stops the timer
+                Timer::clear_interrupt_ack();
+                Timer::clear_interrupt_retransmit();
+                let ack = if disabled {
+                    self.radio.check_ack()?
+                } else {
+                    debug_assert!(timer, "TransmitterWaitAck de: {}, te: {}", disabled, timer);
+                    None
+                };
+                match ack {
+                    Some(length) => {
+                        if let Some(hopper) = self.hopper.as_mut() {
+                            hopper.tx_succeeded();
+                        }
+                        self.stats.count_acked();
+                        self.poll_done(length > 0);
+                    }
+                    None => {
+                        if disabled {
+                            self.stats.count_bad_crc();
+                        }
+                        self.radio.stop(true);
+                        if let Some(channel) = self.hopper.as_mut().and_then(Hopper::tx_failed) {
+                            self.pending_channel = Some(channel);
+                        }
+                        self.poll_done(false);
+                    }
+                }
+                self.send_packet();
             }
             StatePTX::TransmitterWaitAck => {
+                // Read it now, the packet grant is gone after checking the ack or stopping the radio
+                let header = self.radio.tx_header();
+                let maximum_transmit_attempts = header
+                    .and_then(EsbHeader::maximum_transmit_attempts)
+                    .unwrap_or(self.config.maximum_transmit_attempts);
                 let mut retransmit = false;
+                if disabled && header.and_then(EsbHeader::probe).is_some() {
+                    // Read the timer before it is stopped
+                    self.probe_time += Timer::now();
+                }
                 if disabled {
                     // We got an ack, check it
                     Timer::clear_interrupt_ack();
-                    if self.radio.check_ack()? {
+                    if self.radio.check_ack()?.is_some() {
                         // Everything went fine, `clear_interrupt_retransmit` also resets and stops
                         // the timer
                         Timer::clear_interrupt_retransmit();
+                        if let Some(hopper) = self.hopper.as_mut() {
+                            hopper.tx_succeeded();
+                        }
+                        self.stats.count_acked();
+                        self.report(header, TxOutcome::Acked, self.attempts + 1);
                         self.attempts = 0;
                         self.send_packet();
                     } else {
                         // CRC mismatch, wait for retransmission
+                        self.stats.count_bad_crc();
                         retransmit = true;
                     }
                 } else {
@@ -278,14 +753,20 @@ where
                     self.attempts += 1;
                     self.state = StatePTX::TransmitterWaitRetransmit;
                 }
-                if self.attempts > self.config.maximum_transmit_attempts {
+                if self.attempts > maximum_transmit_attempts {
                     Timer::clear_interrupt_retransmit();
 
                     // We reached the maximum number of attempts, `radio.stop()` dropped the radio
                     // grants and we will release the last packet and try the next one
-                    if let Some(old_packet) = self.cons_from_app.read() {
+                    if let Some(old_packet) = self.read_packet() {
                         old_packet.release();
                     }
+                    self.report(header, TxOutcome::Dropped, self.attempts);
+                    self.stats.count_dropped();
+                    // The PRX might be on another channel, or this one might be jammed
+                    if let Some(channel) = self.hopper.as_mut().and_then(Hopper::tx_failed) {
+                        self.pending_channel = Some(channel);
+                    }
                     self.attempts = 0;
                     self.send_packet();
                     return Err(Error::MaximumAttempts);
@@ -300,41 +781,343 @@ where
                 // The timer interrupt cleared and stopped the timer by now
                 self.send_packet();
             }
+            StatePTX::TransmitterAssessChannel => {
+                let minimum = match self.radio.continue_assessment() {
+                    Some(minimum) => minimum,
+                    // Samples left to take, or an interrupt pended by the application
+                    None => return Ok(self.state),
+                };
+                let busy = matches!(self.config.cca(), Some(cca) if cca.is_busy(minimum));
+                if busy {
+                    self.stats.count_channel_busy();
+                }
+                let poll_interval = match self.poller.as_ref() {
+                    Some(poller) if poller.in_flight => Some(poller.interval()),
+                    _ => None,
+                };
+                match (poll_interval, busy) {
+                    (Some(interval), true) => {
+                        // The poll is skipped
+                        if let Some(poller) = self.poller.as_mut() {
+                            poller.in_flight = false;
+                        }
+                        self.wait_poll(interval);
+                    }
+                    (Some(_), false) => self.transmit_poll(),
+                    (None, true) => {
+                        // The packet isn't released, it will be read again after the delay
+                        let delay = self
+                            .config
+                            .cca()
+                            .map_or(1, |cca| cca.delay(self.backoff_rng.next_u32()));
+                        self.busy_assessments = self.busy_assessments.saturating_add(1);
+                        self.timer.set_interrupt_retransmit(delay);
+                        self.state = StatePTX::TransmitterWaitChannel;
+                    }
+                    (None, false) => match self.read_packet() {
+                        Some(packet) => self.transmit_packet(packet),
+                        None => self.send_packet(),
+                    },
+                }
+            }
+            StatePTX::TransmitterWaitChannel => {
+                debug_assert!(
+                    timer,
+                    "TransmitterWaitChannel de: {}, te: {}",
+                    disabled, timer
+                );
+                let maximum_busy = self.config.cca().map_or(u8::MAX, |cca| cca.maximum_busy());
+                if self.busy_assessments >= maximum_busy {
+                    // The channel stayed busy, give up on the packet and try the next one, the queue
+                    // of the assessed packet was kept
+                    if let Some(old_packet) = self.read_packet() {
+                        let header = old_packet.get_header();
+                        old_packet.release();
+                        self.report(Some(header), TxOutcome::ChannelBusy, self.attempts);
+                    }
+                    // Like a run of failed attempts, the channel might be jammed
+                    if let Some(channel) = self.hopper.as_mut().and_then(Hopper::tx_failed) {
+                        self.pending_channel = Some(channel);
+                    }
+                    self.attempts = 0;
+                    self.busy_assessments = 0;
+                    self.send_packet();
+                    return Err(Error::ChannelBusy);
+                }
+                self.send_packet();
+            }
+            StatePTX::TransmitterWaitPoll => {
+                // Packets of the application go before the poll, a user event without a packet
+                // to send, e.g. a cancellation, keeps waiting for the polling interval
+                self.drop_stale_packets();
+                if self.read_packet().is_some() {
+                    Timer::clear_interrupt_retransmit();
+                    self.send_packet();
+                } else if timer {
+                    Timer::clear_interrupt_retransmit();
+                    self.send_poll();
+                }
+            }
         }
         Ok(self.state)
     }
 
-    fn send_packet(&mut self) {
-        if let Some(packet) = self.cons_from_app.read().map(PayloadR::new) {
-            let ack = !packet.no_ack();
-            self.radio.transmit(packet, ack);
-            if ack {
-                self.state = StatePTX::TransmitterTx;
+    /// Whether the packet being sent is a poll
+    fn poll_in_flight(&self) -> bool {
+        matches!(
+            self.poller,
+            Some(Poller {
+                in_flight: true,
+                ..
+            })
+        )
+    }
+
+    /// Ends the poll being sent, `data` tells whether the PRX answered with a payload
+    fn poll_done(&mut self, data: bool) {
+        if let Some(poller) = self.poller.as_mut() {
+            poller.poll_done(data);
+        }
+    }
+
+    /// Waits for the polling interval before sending a poll
+    fn wait_poll(&mut self, interval: u16) {
+        self.radio.disable_disabled_interrupt();
+        self.timer.set_interrupt_retransmit(interval);
+        self.state = StatePTX::TransmitterWaitPoll;
+    }
+
+    /// Sends an empty packet requesting an acknowledgement, so the PRX can send the payload of its
+    /// acknowledgement. The poll is skipped if the channel is busy
+    fn send_poll(&mut self) {
+        match self.poller.as_mut() {
+            Some(poller) => {
+                poller.next_pid();
+                poller.in_flight = true;
+            }
+            None => {
+                self.state = StatePTX::IdleTx;
+                return;
+            }
+        }
+        self.apply_pending_channel();
+        if !self.assess_channel() {
+            self.transmit_poll();
+        }
+    }
+
+    /// Transmits the poll started by `send_poll`
+    fn transmit_poll(&mut self) {
+        let (pipe, pid) = match self.poller.as_ref() {
+            Some(poller) => (poller.polling.pipe(), poller.pid()),
+            None => {
+                self.state = StatePTX::IdleTx;
+                return;
+            }
+        };
+        self.stats.count_sent();
+        self.radio.transmit_poll(pipe, pid);
+        self.state = StatePTX::TransmitterTx;
+    }
+
+    /// Reports the outcome of a packet to the application if it is tagged
+    fn report(&self, header: Option<EsbHeader>, outcome: TxOutcome, attempts: u8) {
+        if let Some(tag) = header.and_then(EsbHeader::tag) {
+            self.tx_reports.push(TxReport::new(tag, outcome, attempts));
+        }
+        if let Some(seq) = header.and_then(EsbHeader::probe) {
+            let round_trip_time = if outcome == TxOutcome::Acked {
+                self.probe_time
             } else {
-                self.state = StatePTX::TransmitterTxNoAck;
+                0
+            };
+            self.tx_reports
+                .set_probe(seq, ProbeResult::new(outcome, attempts, round_trip_time));
+        }
+    }
+
+    /// Whether the packet at the head of the queue was already transmitted or assessed, its queue
+    /// must be kept until the packet is done with
+    fn head_locked(&self) -> bool {
+        self.attempts > 0
+            || self.busy_assessments > 0
+            || self.state == StatePTX::TransmitterAssessChannel
+    }
+
+    /// Reads the packet to be sent, the priority queue is drained first. The queue of a packet
+    /// being retransmitted, or waiting for a busy channel, is kept until the packet is done with
+    fn read_packet(&mut self) -> Option<TxPayload<OutgoingLen, PriorityLen>> {
+        if !self.head_locked() {
+            self.tx_priority = self.cons_priority_from_app.read().is_some();
+        }
+        if self.tx_priority {
+            self.cons_priority_from_app
+                .read()
+                .map(|grant| TxPayload::Priority(PayloadR::new(grant)))
+        } else {
+            self.cons_from_app
+                .read()
+                .map(|grant| TxPayload::Normal(PayloadR::new(grant)))
+        }
+    }
+
+    /// Drops the packets at the head of the queue that were cancelled or whose deadline passed,
+    /// including the packet being retransmitted
+    fn drop_stale_packets(&mut self) {
+        let now = self.clock.map(|clock| clock());
+        while let Some(packet) = self.read_packet() {
+            let header = packet.get_header();
+            let outcome = if self.tx_cancel.is_cancelled(header.pipe(), header.epoch()) {
+                self.tx_cancel.count_discarded();
+                TxOutcome::Cancelled
+            } else {
+                match (now, header.deadline()) {
+                    (Some(now), Some(deadline)) if deadline_passed(now, deadline) => {
+                        TxOutcome::Expired
+                    }
+                    _ => break,
+                }
+            };
+            self.report(Some(header), outcome, self.attempts);
+            self.attempts = 0;
+            self.busy_assessments = 0;
+            packet.release();
+        }
+    }
+
+    fn send_packet(&mut self) {
+        self.drop_stale_packets();
+        // The radio is disabled here, change the channel before starting a new packet
+        if self.attempts == 0 {
+            self.apply_pending_channel();
+        }
+        if let Some(packet) = self.read_packet() {
+            // The packet isn't released, it will be read again once the channel is assessed
+            if !self.assess_channel() {
+                self.transmit_packet(packet);
             }
+        } else if let Some(interval) = self.poller.as_ref().map(Poller::interval) {
+            // Nothing to send, ask the PRX for data after the polling interval
+            self.wait_poll(interval);
         } else {
             self.radio.disable_disabled_interrupt();
             self.state = StatePTX::IdleTx;
         }
     }
+
+    /// Starts the clear channel assessment before a transmission, if enabled, the transmission
+    /// goes on once the assessment is done
+    fn assess_channel(&mut self) -> bool {
+        match self.config.cca() {
+            Some(cca) => {
+                self.radio.start_assessment(cca.samples());
+                self.state = StatePTX::TransmitterAssessChannel;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Transmits the packet at the head of the queue
+    fn transmit_packet(&mut self, packet: TxPayload<OutgoingLen, PriorityLen>) {
+        let ack = !packet.no_ack()
+            && self.config.protocol == Protocol::EnhancedShockBurst
+            && self.config.pipes()[usize::from(packet.pipe())].auto_ack();
+        self.busy_assessments = 0;
+        if let Some(poller) = self.poller.as_mut() {
+            poller.packet_sent(packet.pipe(), packet.get_header().pid());
+        }
+        if packet.get_header().probe().is_some() {
+            // Measures the round trip of the probe from here
+            self.timer.start();
+        }
+        if self.attempts == 0 {
+            self.stats.count_sent();
+        } else {
+            self.stats.count_retransmission();
+        }
+        self.radio.transmit(packet, ack);
+        if ack {
+            self.state = StatePTX::TransmitterTx;
+        } else {
+            self.state = StatePTX::TransmitterTxNoAck;
+        }
+    }
 }
 
-impl<OutgoingLen, IncomingLen, Timer> EsbIrq<OutgoingLen, IncomingLen, Timer, StatePRX>
+impl<OutgoingLen, IncomingLen, Timer, PriorityLen>
+    EsbIrq<OutgoingLen, IncomingLen, Timer, StatePRX, PriorityLen>
 where
     OutgoingLen: ArrayLength<u8>,
     IncomingLen: ArrayLength<u8>,
+    PriorityLen: ArrayLength<u8>,
     Timer: EsbTimer,
 {
+    /// Changes the RF channel, it must be between 0 and 100
+    ///
+    /// The change is applied immediately if the driver is idle or listening for packets, a packet
+    /// being received at that moment is lost. While an acknowledgement is being sent, the change
+    /// is deferred until the end of its transmission.
+    pub fn set_channel(&mut self, channel: u8) -> Result<(), Error> {
+        if channel > 100 {
+            return Err(Error::InvalidParameters);
+        }
+        self.pending_channel = Some(channel);
+        match self.state {
+            StatePRX::IdleRx => {
+                self.apply_pending_channel();
+            }
+            StatePRX::Receiver => self.switch_receiving_channel(),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Applies a new configuration and addresses
+    ///
+    /// The maximum payload sizes, the payload length mode and the protocol mode can't be changed
+    /// at runtime, `Error::InvalidParameters` is returned if they differ from the active ones.
+    ///
+    /// The radio is stopped and reprogrammed, a packet being received or acknowledged at that
+    /// moment is lost. If the driver was receiving, it starts receiving again with the new
+    /// configuration.
+    ///
+    /// The new configuration is applied before receiving starts again, so if
+    /// `Error::IncomingQueueFull` is returned, the configuration was still applied and the driver
+    /// is left idle, [start_receiving](#method.start_receiving) must be called once the
+    /// application has read some packets.
+    pub fn reconfigure(&mut self, config: Config, addresses: Addresses) -> Result<(), Error> {
+        let was_receiving = self.state != StatePRX::IdleRx;
+        self.reprogram(config, addresses)?;
+        self.state = StatePRX::IdleRx;
+        if was_receiving {
+            self.start_receiving()?;
+        }
+        Ok(())
+    }
+
     /// Must be called inside the radio interrupt handler
     pub fn radio_interrupt(&mut self) -> Result<StatePRX, Error> {
         let Events { disabled, timer } = self.check_and_clear_flags();
+        self.take_channel_request();
 
         // We only trigger the interrupt in these three events, if we didn't trigger it then the
         // user did.
         let user_event = !disabled && !timer;
 
-        if user_event && self.state != StatePRX::IdleRx {
+        if timer {
+            // The dwell timer is the only timer used in PRX mode
+            self.dwell_expired();
+        }
+
+        // If the radio didn't trigger the interrupt, only a user event in the idle state requires
+        // more work
+        let start_event = user_event && self.state == StatePRX::IdleRx;
+        if !disabled && !start_event {
+            if self.state == StatePRX::Receiver {
+                // The application might have requested a channel change or the dwell time ended
+                self.switch_receiving_channel();
+            }
             return Ok(self.state);
         }
 
@@ -342,23 +1125,37 @@ where
             StatePRX::Receiver => {
                 debug_assert!(disabled, "Receiver de: {}, te: {}", disabled, timer);
                 // We got a packet, check it
-                match self.radio.check_packet(&mut self.cons_from_app)? {
+                let state = self.radio.check_packet(
+                    &mut self.cons_priority_from_app,
+                    &mut self.cons_from_app,
+                    self.tx_cancel,
+                )?;
+                if state != RxPayloadState::BadCRC && state != RxPayloadState::Rejected {
+                    // The PTX is on this channel, stay here
+                    self.restart_dwell();
+                }
+                match state {
                     // Do nothing, the radio will return to rx
-                    RxPayloadState::BadCRC => {}
+                    RxPayloadState::BadCRC => self.stats.count_bad_crc(),
+                    RxPayloadState::Rejected => {}
                     RxPayloadState::NoAck => {
+                        self.stats.count_received(self.radio.last_rx_pipe());
                         self.prepare_receiver(|this, grant| {
                             this.radio.complete_rx_no_ack(Some(grant));
                             Ok(())
                         })?;
                     }
                     RxPayloadState::RepeatedNoAck => {
+                        self.stats.count_duplicate();
                         // this goes back to rx
                         self.radio.complete_rx_no_ack(None);
                     }
                     RxPayloadState::Ack => {
+                        self.stats.count_received(self.radio.last_rx_pipe());
                         self.state = StatePRX::TransmittingAck;
                     }
                     RxPayloadState::RepeatedAck => {
+                        self.stats.count_duplicate();
                         self.state = StatePRX::TransmittingRepeatedAck;
                     }
                 }
@@ -392,17 +1189,30 @@ where
                 self.start_receiving()?;
             }
         }
+        if self.state == StatePRX::Receiver {
+            // Apply channel changes deferred while the radio was busy
+            self.switch_receiving_channel();
+        }
         Ok(self.state)
     }
 
     /// Changes esb to the receiving state
+    ///
+    /// In the static payload length mode, `Error::InvalidParameters` is returned if an enabled
+    /// pipe has a shorter static length, see [PipeConfig](../struct.PipeConfig.html), since the
+    /// radio can only receive packets of the static payload length.
     pub fn start_receiving(&mut self) -> Result<(), Error> {
+        if self.config.has_short_static_pipe() {
+            return Err(Error::InvalidParameters);
+        }
         if self.state == StatePRX::IdleRx {
+            self.apply_pending_channel();
             self.prepare_receiver(|this, grant| {
                 this.radio.start_receiving(grant, this.config.enabled_pipes);
                 this.state = StatePRX::Receiver;
                 Ok(())
             })?;
+            self.restart_dwell();
         }
         Ok(())
     }
@@ -418,6 +1228,35 @@ where
         self.state = StatePRX::IdleRx;
     }
 
+    // Moves to the next channel of the hop table and restarts the dwell timer
+    fn dwell_expired(&mut self) {
+        if self.state == StatePRX::IdleRx {
+            return;
+        }
+        if let Some(hopper) = self.hopper.as_mut() {
+            self.pending_channel = Some(hopper.hop());
+            self.timer
+                .set_interrupt_retransmit(hopper.hopping.dwell_time());
+        }
+    }
+
+    // Restarts the dwell timer, if hopping is enabled
+    fn restart_dwell(&mut self) {
+        if let Some(hopper) = self.hopper.as_ref() {
+            self.timer
+                .set_interrupt_retransmit(hopper.hopping.dwell_time());
+        }
+    }
+
+    // Applies a pending channel change while listening for packets
+    fn switch_receiving_channel(&mut self) {
+        if self.pending_channel.is_some() {
+            self.radio.stop(false);
+            self.apply_pending_channel();
+            self.radio.resume_receiving();
+        }
+    }
+
     fn prepare_receiver<F>(&mut self, f: F) -> Result<(), Error>
     where
         F: FnOnce(&mut Self, PayloadW<IncomingLen>) -> Result<(), Error>,
@@ -431,8 +1270,50 @@ where
             Ok(())
         } else {
             self.radio.stop(true);
+            // Stops the dwell timer
+            Timer::clear_interrupt_retransmit();
             self.state = StatePRX::IdleRx;
+            self.stats.count_incoming_queue_full();
             Err(Error::IncomingQueueFull)
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scan_count_checks_the_arguments() {
+        assert_eq!(scan_count(&(0..=100), 1, 101), Ok(101));
+        assert_eq!(scan_count(&(40..=40), 1, 1), Ok(1));
+        assert_eq!(scan_count(&(2..=10), 16, 20), Ok(9));
+        assert!(scan_count(&(0..=101), 1, 102).is_err());
+        assert!(scan_count(&RangeInclusive::new(10, 2), 1, 20).is_err());
+        assert!(scan_count(&(2..=10), 0, 20).is_err());
+        assert!(scan_count(&(2..=10), 16, 8).is_err());
+    }
+
+    #[test]
+    fn channel_noise_average() {
+        let noise = ChannelNoise::new(7, 60, 90, 75 * 4, 4);
+        assert_eq!((noise.minimum, noise.maximum, noise.average), (60, 90, 75));
+        // The sum of the largest scan fits, and the average is rounded down
+        let noise = ChannelNoise::new(7, 127, 127, 127 * u32::from(u16::MAX), u16::MAX);
+        assert_eq!(noise.average, 127);
+        assert_eq!(ChannelNoise::new(7, 1, 2, 5, 2).average, 2);
+    }
+
+    #[test]
+    fn deadline_comparison_wraps_around() {
+        assert!(!deadline_passed(100, 100));
+        assert!(!deadline_passed(99, 100));
+        assert!(deadline_passed(101, 100));
+        // The clock wrapped around after the deadline was set
+        assert!(deadline_passed(5, u32::MAX - 5));
+        assert!(!deadline_passed(u32::MAX - 5, 5));
+        // Deadlines up to half of the clock away are still in the future
+        assert!(!deadline_passed(0, i32::MAX as u32));
+        assert!(deadline_passed(0, i32::MAX as u32 + 2));
+    }
+}
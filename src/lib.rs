@@ -1,7 +1,7 @@
 //! Rust implementation of Nordic's Enhanced ShockBurst communication protocol
 //!
 //! This crate implements the Enhanced ShockBurst protocol with dynamic payload size up to 252 bytes
-//! and acknowledgement support.
+//! or a static payload size, and acknowledgement support.
 //!
 //! The communication is performed by two devices in different roles, one acting as the
 //! Primary Transmitter (PTX) and a second one acting as the Primary Receiver (PRX). The
@@ -13,17 +13,23 @@
 //! be sent together with an acknowledgement must be pre-buffered. When a packet that demands an
 //! acknowledgement is received in PRX mode the driver will try to dequeue a payload from the
 //! transmit queue to be sent, an acknowledgement with a zero sized payload will be sent if the
-//! transmit queue is empty.
+//! transmit queue is empty. An optional priority queue, see
+//! [`EsbApp::grant_priority_packet`](app/struct.EsbApp.html#method.grant_priority_packet), is always
+//! drained before the transmit queue.
 //!
 //! # Timing Requirements
 //!
 //! For better communication stability, both the radio and timer interrupts must be top priority,
-//! and the driver's methods should be called at the beginning of the interrupt handler. In
-//! the current implementation, the data rate is fixed at 2Mbps.
+//! and the driver's methods should be called at the beginning of the interrupt handler.
 //!
-//! There are three configurable options that directly affect the timing of the communication:
+//! There are four configurable options that directly affect the timing of the communication:
 //!
-//! - Wait for acknowledgement timeout (us) - Default: 120 microseconds.
+//! - Data rate - Default: 2Mbps.
+//!     - The on-air data rate, see [Data rate](#data-rate). Both devices must use the same data
+//!       rate.
+//!
+//! - Wait for acknowledgement timeout (us) - Default: depends on the data rate, 120 microseconds
+//!   for 2Mbps.
 //!     - It is used in PTX mode while sending a packet that requested for an acknowledgement. It
 //!       must be bigger than the [Ramp-up](#ramp-up) time.
 //!
@@ -49,14 +55,38 @@
 //! Other devices might be compatible with this implementation, however, at this point, the only
 //! tested devices are the ones in the table above.
 //!
+//! # Data rate
+//!
+//! The data rate is selected with [`ConfigBuilder::data_rate`](struct.ConfigBuilder.html#method.data_rate).
+//! The default wait for acknowledgement timeout and its minimum accepted value depend on the
+//! selected rate:
+//!
+//! | Data rate          | Default ack timeout | Minimum ack timeout | Devices     |
+//! | :---               | :---                | :---                | :---        |
+//! | 2Mbps              | 120 us              | 44 us               | All         |
+//! | 1Mbps              | 160 us              | 73 us               | All         |
+//! | 250kbps            | 350 us              | 250 us              | nRF51       |
+//! | 1Mbps BLE          | 160 us              | 73 us               | All         |
+//! | 2Mbps BLE          | 120 us              | 44 us               | nRF52       |
+//!
+//! The BLE modes use a different preamble and can't be used to communicate with nRF24L01+
+//! devices.
+//!
 //! # Ramp-up
 //!
 //! The radio's hardware requires a time before the start or reception of a transmission. This time
 //! is 140 microseconds in the nRF5 devices. However, nRF52 devices have a Fast Ramp-up feature,
-//! where this time is reduced to 40 microseconds. This feature can be enabled by using the
-//! `fast-ru` feature of this crate. Care must be taken when using the Fast Ramp-up while
-//! communicating with devices that do not support it, the timing configuration must take this case
-//! into account.
+//! where this time is reduced to 40 microseconds. This feature can be enabled at runtime with
+//! [`ConfigBuilder::ramp_up`](struct.ConfigBuilder.html#method.ramp_up).
+//!
+//! Care must be taken when using the Fast Ramp-up while communicating with devices that do not
+//! support it, such as nRF51 and nRF24L01+ devices. The ramp-up mode of the peer is declared with
+//! [`ConfigBuilder::peer_ramp_up`](struct.ConfigBuilder.html#method.peer_ramp_up), the driver
+//! waits for the slowest of both when expecting an acknowledgement and the retransmit delay must
+//! account for the difference.
+//!
+//! The `fast-ru` feature of this crate is kept for compatibility, it only changes the default
+//! ramp-up mode of both ends to the fast one.
 //!
 //! # In-queue packet representation
 //!
@@ -65,11 +95,20 @@
 //! [EsbBuffer](buffer/struct.EsbBuffer.html). Moreover, the characteristics of the underlying
 //! BipBuffer must be considered, for more information refer to [bbqueue docs](https://docs.rs/bbqueue).
 //!
-//! | Used by bbqueue framed    | SW USE                         |               ACTUAL DMA PART                                      |
-//! | :---                      | :---                           | :---                                                               |
-//! | frame_size - 1 to 2 bytes | rssi - 1 byte \| pipe - 1 byte | length - 1 byte \| pid_no_ack - 1 byte \| payload - 1 to 252 bytes |
+//! | Used by bbqueue framed    | SW USE                                  |               ACTUAL DMA PART                                      |
+//! | :---                      | :---                                    | :---                                                               |
+//! | frame_size - 1 to 2 bytes | rssi or epoch - 1 byte \| pipe - 1 byte | length - 1 byte \| pid_no_ack - 1 byte \| payload - 1 to 252 bytes |
+//!
+//! Outgoing packets with transmit settings, i.e. a maximum number of attempts, a retransmit
+//! delay, a tag or a deadline, and link probes are preceded by a 9-byte extension, flagged by the
+//! MSb of the first byte:
 //!
-//! The maximum in-queue packet size is 258 bytes (with a 252 bytes payload).
+//! | EXTENSION                                                                                             |
+//! | :---                                                                                                  |
+//! | flags - 1 byte \| tag - 1 byte \| attempts - 1 byte \| retransmit delay - 2 bytes \| deadline - 4 bytes |
+//!
+//! The maximum in-queue packet size is 258 bytes (with a 252 bytes payload), or 267 bytes with
+//! the extension.
 //!
 //! # Compatibility with nRF24L01+
 //!
@@ -78,6 +117,21 @@
 //! (inclusive). That is required because the nRF24L01+ only supports payloads up to that size and
 //! uses a 6-bits effective payload length that must be configured in the nRF5 radio.
 //!
+//! The [CRC](enum.Crc.html) length must match the `EN_CRC` and `CRCO` settings of the nRF24L01+.
+//!
+//! nRF24L01+ devices with the dynamic payload length feature disabled (`DYNPD`) can be reached by
+//! using [`PayloadLength::Static`](enum.PayloadLength.html) with the same length as the `RX_PW_Px`
+//! register of the nRF24L01+. In this mode, acknowledgements never carry a payload. Pipes with a
+//! different `RX_PW_Px` can be given their own static length through
+//! [`PipeConfig`](struct.PipeConfig.html).
+//!
+//! # Legacy ShockBurst
+//!
+//! The nRF2401 and the nRF24L01 in ShockBurst mode don't send the packet control field, which
+//! holds the PID and no-ack bits. These devices can be reached by using
+//! [`Protocol::ShockBurst`](enum.Protocol.html) together with a static payload length. In this
+//! mode packets can't request acknowledgements and the PRX can't detect repeated packets.
+//!
 //! # Examples
 //!
 //! Usage examples can be found at the [demos repository](https://github.com/thalesfragoso/esb-demos).
@@ -87,16 +141,28 @@
 
 pub mod app;
 pub mod buffer;
+pub mod cancel;
+mod encoding;
+pub mod hopping;
 pub mod irq;
 pub mod payload;
 pub mod peripherals;
+pub mod polling;
+pub mod random;
+pub mod report;
+pub mod stats;
 
 // Export crate relevant items
 pub use crate::{
-    app::{Addresses, EsbApp},
+    app::{AddressWidth, Addresses, EsbApp},
     buffer::EsbBuffer,
-    irq::{EsbIrq, IrqTimer},
+    hopping::{HopTable, Hopping},
+    irq::{ChannelNoise, EsbIrq, IrqTimer},
     payload::{EsbHeader, EsbHeaderBuilder},
+    polling::Polling,
+    random::{RandomSource, XorShift32},
+    report::{ProbeResult, TxOutcome, TxReport},
+    stats::LinkStats,
 };
 
 use core::default::Default;
@@ -105,17 +171,29 @@ pub use bbqueue::{consts, ArrayLength, BBBuffer, ConstBBBuffer};
 
 // TODO: Figure it out good values
 const RX_WAIT_FOR_ACK_TIMEOUT_US_2MBPS: u16 = 120;
+const RX_WAIT_FOR_ACK_TIMEOUT_US_1MBPS: u16 = 160;
+#[cfg(feature = "51")]
+const RX_WAIT_FOR_ACK_TIMEOUT_US_250KBPS: u16 = 350;
+const MINIMUM_ACK_TIMEOUT_US_2MBPS: u16 = 44;
+const MINIMUM_ACK_TIMEOUT_US_1MBPS: u16 = 73;
+#[cfg(feature = "51")]
+const MINIMUM_ACK_TIMEOUT_US_250KBPS: u16 = 250;
 const RETRANSMIT_DELAY_US_OFFSET: u16 = 62;
+// Margin added to the on-air time of the acknowledgement by the automatic timing
+const AUTO_ACK_TIMEOUT_US_MARGIN: u32 = 20;
+// The automatic timing assumes the widest address
+const AUTO_TIMING_ADDRESS_BITS: u32 = 40;
 const RETRANSMIT_DELAY: u16 = 500;
 const MAXIMUM_TRANSMIT_ATTEMPTS: u8 = 3;
 const ENABLED_PIPES: u8 = 0xFF;
 
-#[cfg(not(feature = "fast-ru"))]
-pub(crate) const RAMP_UP_TIME: u16 = 140;
+const RAMP_UP_TIME_US_DEFAULT: u16 = 140;
+const RAMP_UP_TIME_US_FAST: u16 = 40;
 
-// This is only true if we enable the fast ramp-up time, which we do
+#[cfg(not(feature = "fast-ru"))]
+const DEFAULT_RAMP_UP: RampUp = RampUp::Default;
 #[cfg(feature = "fast-ru")]
-pub(crate) const RAMP_UP_TIME: u16 = 40;
+const DEFAULT_RAMP_UP: RampUp = RampUp::Fast;
 
 /// Crate-wide error type
 #[derive(Debug, PartialEq, Eq)]
@@ -150,13 +228,375 @@ pub enum Error {
     /// that requested for an acknowledgement, the packet will be removed from the queue and
     /// [EsbIrq](irq/struct.EsbIrq.html) will try to send the next one
     MaximumAttempts,
+
+    /// [EsbIrq](irq/struct.EsbIrq.html) found the channel busy too many times in a row before
+    /// sending a packet, see [Cca](struct.Cca.html). The packet will be removed from the queue and
+    /// [EsbIrq](irq/struct.EsbIrq.html) will try to send the next one
+    ChannelBusy,
+}
+
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let description = match self {
+            Error::IncomingQueueFull => "incoming queue is full",
+            Error::OutgoingQueueFull => "outgoing queue is full",
+            Error::GrantInProgress => "grant already in progress",
+            Error::QueueEmpty => "queue is empty",
+            Error::AlreadySplit => "buffer already split",
+            Error::InvalidParameters => "values out of range",
+            Error::MaximumPacketExceeded => "packet larger than the maximum payload size",
+            Error::InternalError => "internal error",
+            Error::MaximumAttempts => "maximum number of attempts reached",
+            Error::ChannelBusy => "channel busy",
+        };
+        f.write_str(description)
+    }
 }
 
 /// Tx Power
 pub type TxPower = peripherals::TXPOWER_A;
 
+/// On-air data rate
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DataRate {
+    /// 1Mbps Nordic proprietary mode
+    Nrf1Mbit,
+    /// 2Mbps Nordic proprietary mode
+    Nrf2Mbit,
+    /// 250kbps Nordic proprietary mode, only available on nRF51 devices
+    #[cfg(feature = "51")]
+    Nrf250Kbit,
+    /// 1Mbps Bluetooth Low Energy mode
+    Ble1Mbit,
+    /// 2Mbps Bluetooth Low Energy mode, only available on nRF52 devices
+    #[cfg(not(feature = "51"))]
+    Ble2Mbit,
+}
+
+impl DataRate {
+    /// Default wait for acknowledgement timeout for this data rate, in microseconds
+    pub(crate) fn default_ack_timeout(self) -> u16 {
+        match self {
+            DataRate::Nrf1Mbit | DataRate::Ble1Mbit => RX_WAIT_FOR_ACK_TIMEOUT_US_1MBPS,
+            DataRate::Nrf2Mbit => RX_WAIT_FOR_ACK_TIMEOUT_US_2MBPS,
+            #[cfg(feature = "51")]
+            DataRate::Nrf250Kbit => RX_WAIT_FOR_ACK_TIMEOUT_US_250KBPS,
+            #[cfg(not(feature = "51"))]
+            DataRate::Ble2Mbit => RX_WAIT_FOR_ACK_TIMEOUT_US_2MBPS,
+        }
+    }
+
+    /// On-air bit rate, in kbps
+    pub(crate) fn kbps(self) -> u32 {
+        match self {
+            DataRate::Nrf1Mbit | DataRate::Ble1Mbit => 1000,
+            DataRate::Nrf2Mbit => 2000,
+            #[cfg(feature = "51")]
+            DataRate::Nrf250Kbit => 250,
+            #[cfg(not(feature = "51"))]
+            DataRate::Ble2Mbit => 2000,
+        }
+    }
+
+    /// Length of the preamble, in bits
+    pub(crate) fn preamble_bits(self) -> u32 {
+        if self.kbps() == 2000 {
+            16
+        } else {
+            8
+        }
+    }
+
+    /// Minimum wait for acknowledgement timeout for this data rate, in microseconds
+    pub(crate) fn minimum_ack_timeout(self) -> u16 {
+        match self {
+            DataRate::Nrf1Mbit | DataRate::Ble1Mbit => MINIMUM_ACK_TIMEOUT_US_1MBPS,
+            DataRate::Nrf2Mbit => MINIMUM_ACK_TIMEOUT_US_2MBPS,
+            #[cfg(feature = "51")]
+            DataRate::Nrf250Kbit => MINIMUM_ACK_TIMEOUT_US_250KBPS,
+            #[cfg(not(feature = "51"))]
+            DataRate::Ble2Mbit => MINIMUM_ACK_TIMEOUT_US_2MBPS,
+        }
+    }
+}
+
+/// CRC configuration
+///
+/// The polynomials and initial values match the ones used by the nRF24L01+ for each CRC length.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Crc {
+    /// No CRC, the received packets are not checked for errors.
+    ///
+    /// Without a CRC, the PRX can only use the PID to detect retransmissions of a packet.
+    Disabled,
+    /// 1-byte CRC, polynomial `x^8 + x^2 + x + 1` with initial value `0xFF`. Equivalent to
+    /// `EN_CRC = 1` and `CRCO = 0` on the nRF24L01+.
+    OneByte,
+    /// 2-byte CRC, polynomial `x^16 + x^12 + x^5 + 1` with initial value `0xFFFF`. Equivalent to
+    /// `EN_CRC = 1` and `CRCO = 1` on the nRF24L01+.
+    TwoBytes,
+}
+
+/// Payload length mode
+///
+/// The radio uses the same packet configuration for every pipe, so this mode is shared by all of
+/// them.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PayloadLength {
+    /// The length of the payload is sent together with the packet, up to the maximum payload
+    /// size. This is the Dynamic Payload Length (DPL) feature of the nRF24L01+.
+    Dynamic,
+    /// Every packet carries a payload of the given length, in bytes. Equivalent to disabling the
+    /// DPL feature of the nRF24L01+ and setting `RX_PW_Px` to this length.
+    ///
+    /// In this mode, the maximum payload size is the static length and acknowledgements never
+    /// carry a payload. A pipe can have a shorter static length through the maximum payload size
+    /// of its [PipeConfig](struct.PipeConfig.html).
+    Static(u8),
+}
+
+/// Protocol mode
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Protocol {
+    /// Enhanced ShockBurst, packets carry a packet control field with the PID and no-ack bits,
+    /// and may request an acknowledgement
+    EnhancedShockBurst,
+    /// Legacy ShockBurst used by the nRF2401 and by the nRF24L01 in ShockBurst mode. Packets have
+    /// no packet control field at all, therefore they can't request acknowledgements and must use
+    /// a [static payload length](enum.PayloadLength.html).
+    ShockBurst,
+}
+
+/// Radio ramp-up mode
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RampUp {
+    /// Default ramp-up, 140 microseconds, supported by all devices
+    Default,
+    /// Fast ramp-up, 40 microseconds, only supported by nRF52 devices
+    Fast,
+}
+
+impl RampUp {
+    /// Ramp-up time, in microseconds
+    pub(crate) fn time(self) -> u16 {
+        match self {
+            RampUp::Default => RAMP_UP_TIME_US_DEFAULT,
+            RampUp::Fast => RAMP_UP_TIME_US_FAST,
+        }
+    }
+}
+
+/// Per-pipe settings
+///
+/// The payload length mode is shared by all pipes, see [PayloadLength](enum.PayloadLength.html),
+/// but each pipe may have its own acknowledgement setting and a smaller maximum payload size. In
+/// the static payload length mode, the maximum payload size of a pipe is its static length, like
+/// the `RX_PW_Px` registers of the nRF24L01+.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PipeConfig {
+    auto_ack: bool,
+    maximum_payload_size: Option<u8>,
+}
+
+impl Default for PipeConfig {
+    fn default() -> Self {
+        Self {
+            auto_ack: true,
+            maximum_payload_size: None,
+        }
+    }
+}
+
+impl PipeConfig {
+    /// Creates new pipe settings
+    ///
+    /// * `auto_ack` - Enables the acknowledgements of the pipe, equivalent to `EN_AA` on the
+    ///   nRF24L01+. When disabled, the PRX never acknowledges packets received on the pipe, even
+    ///   if they request an acknowledgement, and the PTX never waits for acknowledgements of
+    ///   packets sent to it.
+    /// * `maximum_payload_size` - Maximum payload size of the pipe, `None` uses the maximum
+    ///   payload size of the configuration. Longer packets are dropped by the PRX and rejected by
+    ///   [EsbApp](app/struct.EsbApp.html). In the static payload length mode, this is the static
+    ///   length of the packets sent to the pipe. The radio only receives with the length of
+    ///   [`PayloadLength::Static`](enum.PayloadLength.html), so shorter lengths are only useful to
+    ///   send packets to nRF24L01+ devices, the PRX refuses to receive on such a pipe.
+    pub fn new(auto_ack: bool, maximum_payload_size: Option<u8>) -> Self {
+        Self {
+            auto_ack,
+            maximum_payload_size,
+        }
+    }
+
+    /// Whether the acknowledgements of the pipe are enabled
+    pub fn auto_ack(&self) -> bool {
+        self.auto_ack
+    }
+
+    /// Maximum payload size of the pipe, if it differs from the one of the configuration
+    pub fn maximum_payload_size(&self) -> Option<u8> {
+        self.maximum_payload_size
+    }
+}
+
+/// Randomized retransmit backoff
+///
+/// Without a backoff, PTX devices that collide keep retransmitting at the same cadence and tend to
+/// collide again. With a backoff, the delay before each retransmission is computed as follows:
+///
+/// - The base is the retransmit delay of the packet, see
+///   [EsbHeaderBuilder::retransmit_delay](payload/struct.EsbHeaderBuilder.html#method.retransmit_delay),
+///   or of the configuration.
+/// - With exponential growth, the base is doubled for each failed attempt of the packet.
+/// - A random value between 0 and `jitter` microseconds is added.
+/// - The result is capped to `maximum_delay`, but never goes below the base.
+///
+/// The random values come from a generator that should be seeded differently on each device, see
+/// the [random](random/index.html) module.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Backoff {
+    jitter: u16,
+    exponential: bool,
+    maximum_delay: u16,
+}
+
+impl Backoff {
+    /// Creates a new backoff policy
+    ///
+    /// * `jitter` - Maximum random delay, in microseconds, added to each retransmission.
+    /// * `exponential` - Doubles the delay for each failed attempt of a packet.
+    /// * `maximum_delay` - Maximum retransmit delay in microseconds, it must not be smaller than
+    ///   the retransmit delay of the configuration.
+    pub fn new(jitter: u16, exponential: bool, maximum_delay: u16) -> Self {
+        Self {
+            jitter,
+            exponential,
+            maximum_delay,
+        }
+    }
+
+    /// Maximum random delay, in microseconds, added to each retransmission
+    pub fn jitter(&self) -> u16 {
+        self.jitter
+    }
+
+    /// Whether the delay doubles for each failed attempt of a packet
+    pub fn exponential(&self) -> bool {
+        self.exponential
+    }
+
+    /// Maximum retransmit delay in microseconds
+    pub fn maximum_delay(&self) -> u16 {
+        self.maximum_delay
+    }
+
+    /// Retransmit delay after `attempts` failed attempts, from the `base` delay
+    pub(crate) fn delay(&self, base: u16, attempts: u8, random: u32) -> u16 {
+        let mut delay = u32::from(base);
+        if self.exponential {
+            delay <<= attempts.min(15);
+        }
+        delay += random % (u32::from(self.jitter) + 1);
+        delay
+            .min(u32::from(self.maximum_delay))
+            .max(u32::from(base)) as u16
+    }
+}
+
+/// Clear channel assessment, also known as listen before talk
+///
+/// Before each transmission, including retransmissions, the PTX ramps up in RX mode and takes
+/// `samples` RSSI samples on the channel of the packet. If the strongest sample is at or above
+/// `-threshold` dBm, the channel is busy: the transmission is deferred by a random delay between
+/// 1 and `busy_delay` microseconds and the channel is assessed again. After `maximum_busy`
+/// busy assessments in a row, the packet is dropped and
+/// [Error::ChannelBusy](enum.Error.html#variant.ChannelBusy) is returned. While a packet waits for
+/// the channel, packets committed to the priority queue wait behind it.
+///
+/// The assessment runs in the background: the radio interrupt starts the receiver, then runs once
+/// when the receiver is ready and once per RSSI sample, so it never waits for the ramp-up. Each
+/// transmission is still delayed by the RX ramp-up time, the sampling and the time to disable the
+/// receiver, which adds to the retransmit delay, including the one computed by
+/// [auto_timing](struct.ConfigBuilder.html#method.auto_timing). Busy assessments are counted in
+/// [LinkStats](stats/struct.LinkStats.html) and the random delays come from the same generator as
+/// the [Backoff](struct.Backoff.html).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Cca {
+    threshold: u8,
+    samples: u8,
+    busy_delay: u16,
+    maximum_busy: u8,
+}
+
+impl Cca {
+    /// Creates a new clear channel assessment policy
+    ///
+    /// * `threshold` - Magnitude of the RSSI, in dBm, at or above which the channel is busy, i.e.
+    ///   a value of 70 means that a signal of -70 dBm or stronger makes the channel busy.
+    /// * `samples` - Number of RSSI samples taken for each assessment, must not be zero.
+    /// * `busy_delay` - Maximum random delay, in microseconds, before assessing a busy channel
+    ///   again, must not be zero.
+    /// * `maximum_busy` - Busy assessments in a row after which the packet is dropped, must not be
+    ///   zero.
+    pub fn new(threshold: u8, samples: u8, busy_delay: u16, maximum_busy: u8) -> Self {
+        Self {
+            threshold,
+            samples,
+            busy_delay,
+            maximum_busy,
+        }
+    }
+
+    /// Magnitude of the RSSI, in dBm, at or above which the channel is busy
+    pub fn threshold(&self) -> u8 {
+        self.threshold
+    }
+
+    /// Number of RSSI samples taken for each assessment
+    pub fn samples(&self) -> u8 {
+        self.samples
+    }
+
+    /// Maximum random delay, in microseconds, before assessing a busy channel again
+    pub fn busy_delay(&self) -> u16 {
+        self.busy_delay
+    }
+
+    /// Busy assessments in a row after which the packet is dropped
+    pub fn maximum_busy(&self) -> u8 {
+        self.maximum_busy
+    }
+
+    /// Whether the channel is busy, from the lowest RSSI magnitude sampled
+    pub(crate) fn is_busy(&self, minimum_rssi: u8) -> bool {
+        minimum_rssi <= self.threshold
+    }
+
+    /// Delay before assessing a busy channel again
+    pub(crate) fn delay(&self, random: u32) -> u16 {
+        (random % u32::from(self.busy_delay)) as u16 + 1
+    }
+}
+
 /// Protocol configuration
+///
+/// A configuration can be stored or transferred by using its versioned binary encoding, see
+/// [to_bytes](#method.to_bytes) and [from_bytes](#method.from_bytes). With the `serde` feature,
+/// it also implements `Serialize` and `Deserialize`, deserialization goes through the same
+/// validation as [ConfigBuilder](struct.ConfigBuilder.html).
 #[derive(Copy, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(into = "encoding::ConfigRepr", try_from = "encoding::ConfigRepr")
+)]
 pub struct Config {
     /// Number of microseconds to wait for an acknowledgement before timing out
     wait_for_ack_timeout: u16,
@@ -175,6 +615,24 @@ pub struct Config {
     /// This allows for a more efficient usage of the receiver queue and makes this driver
     /// compatible with nRF24L01+ modules when this size is 32 bytes or less
     maximum_payload_size: u8,
+    /// On-air data rate
+    data_rate: DataRate,
+    /// CRC length
+    crc: Crc,
+    /// Payload length mode
+    payload_length: PayloadLength,
+    /// Protocol mode
+    protocol: Protocol,
+    /// Per-pipe settings
+    pipes: [PipeConfig; 8],
+    /// Ramp-up mode of this device
+    ramp_up: RampUp,
+    /// Ramp-up mode of the other end of the communication
+    peer_ramp_up: RampUp,
+    /// Randomized retransmit backoff
+    backoff: Option<Backoff>,
+    /// Clear channel assessment before transmitting
+    cca: Option<Cca>,
 }
 
 impl Default for Config {
@@ -186,6 +644,177 @@ impl Default for Config {
             enabled_pipes: ENABLED_PIPES,
             tx_power: TxPower::_0DBM,
             maximum_payload_size: 252,
+            data_rate: DataRate::Nrf2Mbit,
+            crc: Crc::TwoBytes,
+            payload_length: PayloadLength::Dynamic,
+            protocol: Protocol::EnhancedShockBurst,
+            pipes: [PipeConfig::default(); 8],
+            ramp_up: DEFAULT_RAMP_UP,
+            peer_ramp_up: DEFAULT_RAMP_UP,
+            backoff: None,
+            cca: None,
+        }
+    }
+}
+
+impl Config {
+    /// Number of microseconds to wait for an acknowledgement before timing out
+    pub fn wait_for_ack_timeout(&self) -> u16 {
+        self.wait_for_ack_timeout
+    }
+
+    /// Delay, in microseconds, between retransmissions
+    pub fn retransmit_delay(&self) -> u16 {
+        self.retransmit_delay
+    }
+
+    /// Maximum number of transmit attempts when an acknowledgement is not received
+    pub fn maximum_transmit_attempts(&self) -> u8 {
+        self.maximum_transmit_attempts
+    }
+
+    /// Bit mask of the pipes that the radio listens while receiving, the LSb is pipe zero
+    pub fn enabled_pipes(&self) -> u8 {
+        self.enabled_pipes
+    }
+
+    /// Tx Power
+    pub fn tx_power(&self) -> TxPower {
+        self.tx_power
+    }
+
+    /// Maximum payload size in bytes
+    pub fn maximum_payload_size(&self) -> u8 {
+        self.maximum_payload_size
+    }
+
+    /// On-air data rate
+    pub fn data_rate(&self) -> DataRate {
+        self.data_rate
+    }
+
+    /// CRC length
+    pub fn crc(&self) -> Crc {
+        self.crc
+    }
+
+    /// Payload length mode
+    pub fn payload_length(&self) -> PayloadLength {
+        self.payload_length
+    }
+
+    /// Protocol mode
+    pub fn protocol(&self) -> Protocol {
+        self.protocol
+    }
+
+    /// Per-pipe settings, in pipe order
+    pub fn pipes(&self) -> &[PipeConfig; 8] {
+        &self.pipes
+    }
+
+    /// Ramp-up mode of this device
+    pub fn ramp_up(&self) -> RampUp {
+        self.ramp_up
+    }
+
+    /// Ramp-up mode of the other end of the communication
+    pub fn peer_ramp_up(&self) -> RampUp {
+        self.peer_ramp_up
+    }
+
+    /// Gets the retransmit backoff, if any
+    pub fn backoff(&self) -> Option<Backoff> {
+        self.backoff
+    }
+
+    /// Gets the clear channel assessment, if any
+    pub fn cca(&self) -> Option<Cca> {
+        self.cca
+    }
+
+    /// Smallest retransmit delay accepted for this configuration, in microseconds
+    pub(crate) fn minimum_retransmit_delay(&self) -> u32 {
+        // A slower peer delays the acknowledgement by the difference of the ramp-up times
+        let ramp_up_time = self.ramp_up.time();
+        let peer_delay = self.ack_ramp_up_time() - ramp_up_time;
+        let minimum = u32::from(self.wait_for_ack_timeout)
+            + u32::from(RETRANSMIT_DELAY_US_OFFSET)
+            + u32::from(peer_delay);
+        minimum.max(u32::from(ramp_up_time)) + 1
+    }
+
+    /// Time to wait after the end of a transmission for the acknowledgement to start, both ends
+    /// ramp up at the same time, so the slowest one dominates
+    pub(crate) fn ack_ramp_up_time(&self) -> u16 {
+        self.ramp_up.time().max(self.peer_ramp_up.time())
+    }
+
+    /// On-air time, in microseconds, of a packet with the given payload size and a 5-byte
+    /// address, from the start of the preamble to the end of the CRC
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use esb::{ConfigBuilder, DataRate};
+    ///
+    /// let config = ConfigBuilder::default()
+    ///     .data_rate(DataRate::Nrf1Mbit)
+    ///     .max_payload_size(32)
+    ///     .check()
+    ///     .unwrap();
+    /// // 8 bits of preamble, 40 bits of address, 9 bits of packet control field, 32 bits of
+    /// // payload and 16 bits of CRC
+    /// assert_eq!(config.packet_airtime(4), 105);
+    /// ```
+    pub fn packet_airtime(&self, payload_size: u8) -> u16 {
+        let control_bits = match (self.protocol, self.payload_length) {
+            (Protocol::ShockBurst, _) => 0,
+            (_, PayloadLength::Static(_)) => 9,
+            (_, PayloadLength::Dynamic) if self.maximum_payload_size <= 32 => 9,
+            (_, PayloadLength::Dynamic) => 11,
+        };
+        let crc_bits = match self.crc {
+            Crc::Disabled => 0,
+            Crc::OneByte => 8,
+            Crc::TwoBytes => 16,
+        };
+        let bits = self.data_rate.preamble_bits()
+            + AUTO_TIMING_ADDRESS_BITS
+            + control_bits
+            + 8 * u32::from(payload_size)
+            + crc_bits;
+        let kbps = self.data_rate.kbps();
+        // Rounds up, the biggest result is a bit over 8 ms
+        let airtime = bits * 1000 / kbps;
+        if airtime * kbps < bits * 1000 {
+            (airtime + 1) as u16
+        } else {
+            airtime as u16
+        }
+    }
+
+    /// Maximum payload size of each pipe, taking into account the per-pipe settings
+    pub(crate) fn pipe_maximum_payload_sizes(&self) -> [u8; 8] {
+        let mut sizes = [self.maximum_payload_size; 8];
+        for (size, pipe) in sizes.iter_mut().zip(self.pipes.iter()) {
+            if let Some(pipe_size) = pipe.maximum_payload_size {
+                *size = pipe_size;
+            }
+        }
+        sizes
+    }
+
+    /// Whether an enabled pipe has a static length shorter than the static payload length, the
+    /// radio only receives with the latter
+    pub(crate) fn has_short_static_pipe(&self) -> bool {
+        match self.payload_length {
+            PayloadLength::Static(length) => self
+                .pipe_maximum_payload_sizes()
+                .iter()
+                .enumerate()
+                .any(|(pipe, &size)| self.enabled_pipes & (1 << pipe) != 0 && size < length),
+            PayloadLength::Dynamic => false,
         }
     }
 }
@@ -216,71 +845,514 @@ impl Default for Config {
 ///
 /// | Field                               | Default Value |
 /// | :---                                | :---          |
+/// | Data rate                           | 2Mbps         |
 /// | Ack Timeout                         | 120 us        |
 /// | Retransmit Delay                    | 500 us        |
 /// | Maximum number of transmit attempts | 3             |
 /// | Enabled Pipes                       | 0xFF          |
 /// | Tx Power                            | 0dBm          |
 /// | Maximum payload size                | 252 bytes     |
+/// | CRC                                 | 2 bytes       |
+/// | Payload length                      | Dynamic       |
+/// | Protocol                            | ESB           |
+/// | Per-pipe settings                   | Auto-ack enabled, global maximum payload size |
+/// | Ramp-up (own and peer)              | Default, fast with the `fast-ru` feature |
+/// | Retransmit backoff                  | None          |
+/// | Clear channel assessment            | None          |
 ///
-pub struct ConfigBuilder(Config);
-
-impl Default for ConfigBuilder {
-    fn default() -> Self {
-        Self(Config::default())
-    }
+/// If the ack timeout is not set explicitly, the default value of the selected data rate will be
+/// used, see the [crate level docs](index.html#data-rate).
+#[derive(Default)]
+pub struct ConfigBuilder {
+    config: Config,
+    wait_for_ack_timeout: Option<u16>,
+    auto_timing: Option<u8>,
 }
 
 impl ConfigBuilder {
-    /// Sets number of microseconds to wait for an acknowledgement before timing out
+    /// Sets the on-air data rate
+    pub fn data_rate(mut self, data_rate: DataRate) -> Self {
+        self.config.data_rate = data_rate;
+        self
+    }
+
+    /// Sets number of microseconds to wait for an acknowledgement before timing out, must not be
+    /// smaller than the minimum ack timeout of the selected data rate
     pub fn wait_for_ack_timeout(mut self, micros: u16) -> Self {
-        self.0.wait_for_ack_timeout = micros;
+        self.wait_for_ack_timeout = Some(micros);
         self
     }
 
     // TODO: document 62
     /// Sets retransmit delay, must be bigger than `wait_for_ack_timeout` field plus 62 and bigger
-    /// than the ramp-up time (140us without fast-ru and 40us with fast-ru)
+    /// than the ramp-up time (140us by default and 40us with the fast ramp-up). When the peer
+    /// ramps up slower than this device, the difference must also be added
     pub fn retransmit_delay(mut self, micros: u16) -> Self {
-        self.0.retransmit_delay = micros;
+        self.config.retransmit_delay = micros;
         self
     }
 
     /// Sets maximum number of transmit attempts
     pub fn maximum_transmit_attempts(mut self, n: u8) -> Self {
-        self.0.maximum_transmit_attempts = n;
+        self.config.maximum_transmit_attempts = n;
         self
     }
 
     /// Sets enabled pipes for receiving
     pub fn enabled_pipes(mut self, enabled_pipes: u8) -> Self {
-        self.0.enabled_pipes = enabled_pipes;
+        self.config.enabled_pipes = enabled_pipes;
         self
     }
 
     /// Sets the tx power
     pub fn tx_power(mut self, tx_power: TxPower) -> Self {
-        self.0.tx_power = tx_power;
+        self.config.tx_power = tx_power;
         self
     }
 
     /// Sets the maximum payload size
     pub fn max_payload_size(mut self, payload_size: u8) -> Self {
-        self.0.maximum_payload_size = payload_size;
+        self.config.maximum_payload_size = payload_size;
         self
     }
 
-    pub fn check(self) -> Result<Config, Error> {
-        let bad_ack_timeout = self.0.wait_for_ack_timeout < 44;
-        let bad_retransmit_delay = self.0.retransmit_delay
-            <= self.0.wait_for_ack_timeout + RETRANSMIT_DELAY_US_OFFSET
-            || self.0.retransmit_delay <= RAMP_UP_TIME;
-        let bad_size = self.0.maximum_payload_size > 252;
+    /// Sets the CRC length
+    pub fn crc(mut self, crc: Crc) -> Self {
+        self.config.crc = crc;
+        self
+    }
 
-        if bad_ack_timeout || bad_retransmit_delay || bad_size {
+    /// Sets the payload length mode, a static length replaces the maximum payload size
+    pub fn payload_length(mut self, payload_length: PayloadLength) -> Self {
+        self.config.payload_length = payload_length;
+        self
+    }
+
+    /// Sets the protocol mode, the legacy ShockBurst mode requires a static payload length
+    pub fn protocol(mut self, protocol: Protocol) -> Self {
+        self.config.protocol = protocol;
+        self
+    }
+
+    /// Sets the ramp-up mode of this device, the fast ramp-up is only available on nRF52 devices
+    pub fn ramp_up(mut self, ramp_up: RampUp) -> Self {
+        self.config.ramp_up = ramp_up;
+        self
+    }
+
+    /// Sets the ramp-up mode of the other end of the communication, e.g. `RampUp::Default` for
+    /// nRF51 and nRF24L01+ devices
+    pub fn peer_ramp_up(mut self, ramp_up: RampUp) -> Self {
+        self.config.peer_ramp_up = ramp_up;
+        self
+    }
+
+    /// Computes the wait for acknowledgement timeout and the retransmit delay from the data rate,
+    /// the packet format, the ramp-up times of both ends and the largest acknowledgement payload
+    /// that will be sent by the PRX. The values set with
+    /// [wait_for_ack_timeout](#method.wait_for_ack_timeout) and
+    /// [retransmit_delay](#method.retransmit_delay) are ignored.
+    ///
+    /// The ack timeout covers the on-air time of the largest acknowledgement plus a small margin,
+    /// and is never smaller than the minimum of the data rate. The retransmit delay is the
+    /// smallest value that retransmits only after the ack timeout expired. Both timers start at
+    /// the end of the transmission, so the size of the outgoing packets doesn't affect them, it
+    /// only affects the time between retransmissions, see
+    /// [packet_airtime](struct.Config.html#method.packet_airtime). The same goes for the
+    /// [clear channel assessment](struct.Cca.html), which delays each retransmission by about the
+    /// RX ramp-up time on top of the computed retransmit delay.
+    ///
+    /// The computed values can be read from the resulting [Config](struct.Config.html).
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use esb::{ConfigBuilder, DataRate};
+    ///
+    /// let config = ConfigBuilder::default()
+    ///     .data_rate(DataRate::Nrf1Mbit)
+    ///     .max_payload_size(32)
+    ///     .auto_timing(32)
+    ///     .check()
+    ///     .unwrap();
+    /// assert_eq!(config.wait_for_ack_timeout(), config.packet_airtime(32) + 20);
+    /// assert!(config.retransmit_delay() > config.wait_for_ack_timeout());
+    /// ```
+    pub fn auto_timing(mut self, maximum_ack_payload_size: u8) -> Self {
+        self.auto_timing = Some(maximum_ack_payload_size);
+        self
+    }
+
+    /// Sets the randomized retransmit backoff, see [Backoff](struct.Backoff.html)
+    pub fn backoff(mut self, backoff: Option<Backoff>) -> Self {
+        self.config.backoff = backoff;
+        self
+    }
+
+    /// Sets the clear channel assessment done by the PTX before transmitting, see
+    /// [Cca](struct.Cca.html)
+    pub fn cca(mut self, cca: Option<Cca>) -> Self {
+        self.config.cca = cca;
+        self
+    }
+
+    /// Sets the per-pipe settings, in pipe order. The maximum payload size of a pipe must not be
+    /// bigger than the maximum payload size of the configuration, in the static payload length
+    /// mode it is the static length of the pipe
+    pub fn pipes(mut self, pipes: [PipeConfig; 8]) -> Self {
+        self.config.pipes = pipes;
+        self
+    }
+
+    // Computes the minimal timing values for the automatic timing
+    fn compute_timing(&mut self, ack_payload_size: u8) -> Result<(), Error> {
+        let config = &mut self.config;
+        let ack_payload_size = match config.payload_length {
+            // Acknowledgements don't have a payload in the static payload length mode
+            PayloadLength::Static(_) => 0,
+            PayloadLength::Dynamic => ack_payload_size.min(config.maximum_payload_size),
+        };
+        let wait_for_ack_timeout = (u32::from(config.packet_airtime(ack_payload_size))
+            + AUTO_ACK_TIMEOUT_US_MARGIN)
+            .max(u32::from(config.data_rate.minimum_ack_timeout()));
+
+        // The retransmit timer, which already discounts our ramp-up, must expire after the ack
+        // timer, which accounts for the slowest ramp-up
+        let ramp_up_time = u32::from(config.ramp_up.time());
+        let ack_ramp_up_time = u32::from(config.ack_ramp_up_time());
+        let retransmit_delay = (wait_for_ack_timeout + ack_ramp_up_time + ramp_up_time + 1).max(
+            wait_for_ack_timeout
+                + u32::from(RETRANSMIT_DELAY_US_OFFSET)
+                + (ack_ramp_up_time - ramp_up_time)
+                + 1,
+        );
+
+        if retransmit_delay > u32::from(u16::MAX) {
+            return Err(Error::InvalidParameters);
+        }
+        config.wait_for_ack_timeout = wait_for_ack_timeout as u16;
+        config.retransmit_delay = retransmit_delay as u16;
+        Ok(())
+    }
+
+    pub fn check(mut self) -> Result<Config, Error> {
+        let data_rate = self.config.data_rate;
+        self.config.wait_for_ack_timeout = self
+            .wait_for_ack_timeout
+            .unwrap_or_else(|| data_rate.default_ack_timeout());
+        if let PayloadLength::Static(length) = self.config.payload_length {
+            self.config.maximum_payload_size = length;
+        }
+        if let Some(ack_payload_size) = self.auto_timing {
+            self.compute_timing(ack_payload_size)?;
+        }
+
+        let bad_ack_timeout = self.config.wait_for_ack_timeout < data_rate.minimum_ack_timeout();
+        let bad_retransmit_delay =
+            u32::from(self.config.retransmit_delay) < self.config.minimum_retransmit_delay();
+        let bad_ramp_up = cfg!(feature = "51") && self.config.ramp_up == RampUp::Fast;
+        let bad_backoff = match self.config.backoff {
+            Some(backoff) => backoff.maximum_delay < self.config.retransmit_delay,
+            None => false,
+        };
+        let bad_cca = match self.config.cca {
+            Some(cca) => cca.samples == 0 || cca.busy_delay == 0 || cca.maximum_busy == 0,
+            None => false,
+        };
+        let bad_size = self.config.maximum_payload_size > 252;
+        let bad_protocol = self.config.protocol == Protocol::ShockBurst
+            && self.config.payload_length == PayloadLength::Dynamic;
+        let maximum_payload_size = self.config.maximum_payload_size;
+        let bad_pipes = self
+            .config
+            .pipes
+            .iter()
+            .any(|pipe| match pipe.maximum_payload_size {
+                Some(size) => size > maximum_payload_size,
+                None => false,
+            });
+
+        if bad_ack_timeout
+            || bad_retransmit_delay
+            || bad_size
+            || bad_protocol
+            || bad_pipes
+            || bad_ramp_up
+            || bad_backoff
+            || bad_cca
+        {
             Err(Error::InvalidParameters)
         } else {
-            Ok(self.0)
+            Ok(self.config)
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ack_timeout_defaults_to_the_data_rate() {
+        let config = ConfigBuilder::default()
+            .data_rate(DataRate::Nrf1Mbit)
+            .check()
+            .unwrap();
+        assert_eq!(
+            config.wait_for_ack_timeout,
+            RX_WAIT_FOR_ACK_TIMEOUT_US_1MBPS
+        );
+
+        let config = ConfigBuilder::default().check().unwrap();
+        assert_eq!(
+            config.wait_for_ack_timeout,
+            RX_WAIT_FOR_ACK_TIMEOUT_US_2MBPS
+        );
+
+        // An explicit timeout is kept whatever the data rate
+        let config = ConfigBuilder::default()
+            .wait_for_ack_timeout(200)
+            .data_rate(DataRate::Ble1Mbit)
+            .check()
+            .unwrap();
+        assert_eq!(config.wait_for_ack_timeout, 200);
+    }
+
+    #[test]
+    fn ack_timeout_minimum_depends_on_the_data_rate() {
+        let config = |data_rate, micros| {
+            ConfigBuilder::default()
+                .data_rate(data_rate)
+                .wait_for_ack_timeout(micros)
+                .check()
+        };
+        assert!(config(DataRate::Nrf2Mbit, 44).is_ok());
+        assert!(config(DataRate::Nrf2Mbit, 43).is_err());
+        assert!(config(DataRate::Nrf1Mbit, 73).is_ok());
+        assert!(config(DataRate::Nrf1Mbit, 72).is_err());
+    }
+
+    #[test]
+    fn retransmit_delay_must_cover_the_ack_timeout() {
+        let config = |ack_timeout, retransmit_delay| {
+            ConfigBuilder::default()
+                .wait_for_ack_timeout(ack_timeout)
+                .retransmit_delay(retransmit_delay)
+                .check()
+        };
+        assert!(config(120, 120 + RETRANSMIT_DELAY_US_OFFSET + 1).is_ok());
+        assert!(config(120, 120 + RETRANSMIT_DELAY_US_OFFSET).is_err());
+        // The sum doesn't overflow
+        assert!(config(u16::MAX, u16::MAX).is_err());
+    }
+
+    #[test]
+    fn shockburst_requires_a_static_length() {
+        let config = |payload_length| {
+            ConfigBuilder::default()
+                .protocol(Protocol::ShockBurst)
+                .payload_length(payload_length)
+                .check()
+        };
+        assert!(config(PayloadLength::Dynamic).is_err());
+        assert!(config(PayloadLength::Static(32)).is_ok());
+    }
+
+    #[test]
+    fn pipe_maximum_payload_sizes_default_to_the_configuration() {
+        let mut pipes = [PipeConfig::default(); 8];
+        pipes[3] = PipeConfig::new(false, Some(8));
+        pipes[7] = PipeConfig::new(true, Some(32));
+        let config = ConfigBuilder::default()
+            .max_payload_size(32)
+            .pipes(pipes)
+            .check()
+            .unwrap();
+        assert_eq!(
+            config.pipe_maximum_payload_sizes(),
+            [32, 32, 32, 8, 32, 32, 32, 32]
+        );
+        assert!(!config.pipes()[3].auto_ack());
+        assert!(config.pipes()[7].auto_ack());
+    }
+
+    #[test]
+    fn pipe_maximum_payload_sizes_are_checked() {
+        let config = |payload_length, size| {
+            let mut pipes = [PipeConfig::default(); 8];
+            pipes[1] = PipeConfig::new(true, Some(size));
+            ConfigBuilder::default()
+                .max_payload_size(32)
+                .payload_length(payload_length)
+                .pipes(pipes)
+                .check()
+        };
+        assert!(config(PayloadLength::Dynamic, 32).is_ok());
+        assert!(config(PayloadLength::Dynamic, 33).is_err());
+        assert!(config(PayloadLength::Static(16), 16).is_ok());
+        assert!(config(PayloadLength::Static(16), 8).is_ok());
+        assert!(config(PayloadLength::Static(16), 17).is_err());
+    }
+
+    #[test]
+    fn short_static_pipes_are_found() {
+        let config = |payload_length, enabled_pipes| {
+            let mut pipes = [PipeConfig::default(); 8];
+            pipes[1] = PipeConfig::new(true, Some(8));
+            ConfigBuilder::default()
+                .max_payload_size(16)
+                .payload_length(payload_length)
+                .enabled_pipes(enabled_pipes)
+                .pipes(pipes)
+                .check()
+                .unwrap()
+        };
+        assert!(config(PayloadLength::Static(16), 0b11).has_short_static_pipe());
+        // Only the enabled pipes are received
+        assert!(!config(PayloadLength::Static(16), 0b01).has_short_static_pipe());
+        assert!(!config(PayloadLength::Dynamic, 0b11).has_short_static_pipe());
+    }
+
+    #[test]
+    fn ack_waits_for_the_slowest_ramp_up() {
+        let config = |ramp_up, peer_ramp_up| {
+            ConfigBuilder::default()
+                .ramp_up(ramp_up)
+                .peer_ramp_up(peer_ramp_up)
+                .check()
+                .unwrap()
+        };
+        assert_eq!(
+            config(RampUp::Fast, RampUp::Fast).ack_ramp_up_time(),
+            RAMP_UP_TIME_US_FAST
+        );
+        assert_eq!(
+            config(RampUp::Fast, RampUp::Default).ack_ramp_up_time(),
+            RAMP_UP_TIME_US_DEFAULT
+        );
+        assert_eq!(
+            config(RampUp::Default, RampUp::Fast).ack_ramp_up_time(),
+            RAMP_UP_TIME_US_DEFAULT
+        );
+    }
+
+    #[test]
+    fn retransmit_delay_accounts_for_a_slower_peer() {
+        let config = |peer_ramp_up, retransmit_delay| {
+            ConfigBuilder::default()
+                .ramp_up(RampUp::Fast)
+                .peer_ramp_up(peer_ramp_up)
+                .wait_for_ack_timeout(120)
+                .retransmit_delay(retransmit_delay)
+                .check()
+        };
+        let minimum = 120 + RETRANSMIT_DELAY_US_OFFSET + 1;
+        let peer_delay = RAMP_UP_TIME_US_DEFAULT - RAMP_UP_TIME_US_FAST;
+        assert!(config(RampUp::Fast, minimum).is_ok());
+        assert!(config(RampUp::Default, minimum).is_err());
+        assert!(config(RampUp::Default, minimum + peer_delay - 1).is_err());
+        assert!(config(RampUp::Default, minimum + peer_delay).is_ok());
+    }
+
+    #[test]
+    fn minimum_retransmit_delay_depends_on_the_ramp_up() {
+        let config = |ramp_up, peer_ramp_up| {
+            ConfigBuilder::default()
+                .ramp_up(ramp_up)
+                .peer_ramp_up(peer_ramp_up)
+                .wait_for_ack_timeout(120)
+                .retransmit_delay(1000)
+                .check()
+                .unwrap()
+                .minimum_retransmit_delay()
+        };
+        let minimum = u32::from(120 + RETRANSMIT_DELAY_US_OFFSET) + 1;
+        let peer_delay = u32::from(RAMP_UP_TIME_US_DEFAULT - RAMP_UP_TIME_US_FAST);
+        assert_eq!(config(RampUp::Default, RampUp::Default), minimum);
+        assert_eq!(config(RampUp::Fast, RampUp::Fast), minimum);
+        assert_eq!(config(RampUp::Default, RampUp::Fast), minimum);
+        assert_eq!(config(RampUp::Fast, RampUp::Default), minimum + peer_delay);
+
+        // The retransmit delay also covers the ramp-up time of a very short ack timeout
+        let config = ConfigBuilder::default()
+            .data_rate(DataRate::Nrf2Mbit)
+            .wait_for_ack_timeout(44)
+            .check()
+            .unwrap();
+        assert!(config.minimum_retransmit_delay() > u32::from(RAMP_UP_TIME_US_DEFAULT));
+    }
+
+    #[test]
+    fn backoff_adds_jitter() {
+        let backoff = Backoff::new(100, false, 1_000);
+        assert_eq!(backoff.delay(250, 3, 0), 250);
+        assert_eq!(backoff.delay(250, 3, 100), 350);
+        // The random value is reduced to 0..=jitter
+        assert_eq!(backoff.delay(250, 3, 101), 250);
+        assert_eq!(
+            backoff.delay(250, 3, u32::MAX),
+            250 + (u32::MAX % 101) as u16
+        );
+    }
+
+    #[test]
+    fn backoff_grows_exponentially() {
+        let backoff = Backoff::new(0, true, 10_000);
+        assert_eq!(backoff.delay(250, 0, 0), 250);
+        assert_eq!(backoff.delay(250, 1, 0), 500);
+        assert_eq!(backoff.delay(250, 3, 0), 2_000);
+        assert_eq!(backoff.delay(250, 6, 0), 10_000);
+        // Large attempt counts don't overflow
+        assert_eq!(backoff.delay(u16::MAX, u8::MAX, u32::MAX), u16::MAX);
+    }
+
+    #[test]
+    fn backoff_is_capped() {
+        let backoff = Backoff::new(1_000, false, 600);
+        assert_eq!(backoff.delay(250, 0, 999), 600);
+        // The cap never goes below the base delay
+        assert_eq!(backoff.delay(800, 0, 999), 800);
+    }
+
+    #[test]
+    fn backoff_maximum_delay_is_checked() {
+        let config = |maximum_delay| {
+            ConfigBuilder::default()
+                .retransmit_delay(500)
+                .backoff(Some(Backoff::new(100, true, maximum_delay)))
+                .check()
+        };
+        assert!(config(499).is_err());
+        assert!(config(500).is_ok());
+    }
+
+    #[test]
+    fn cca_busy_threshold() {
+        let cca = Cca::new(70, 4, 500, 10);
+        // The RSSI is a magnitude, a smaller value is a stronger signal
+        assert!(cca.is_busy(60));
+        assert!(cca.is_busy(70));
+        assert!(!cca.is_busy(71));
+    }
+
+    #[test]
+    fn cca_delay_range() {
+        let cca = Cca::new(70, 4, 500, 10);
+        assert_eq!(cca.delay(0), 1);
+        assert_eq!(cca.delay(499), 500);
+        assert_eq!(cca.delay(500), 1);
+        assert!((0..2_000)
+            .map(|random| cca.delay(random * 7_919))
+            .all(|delay| (1..=500).contains(&delay)));
+        assert_eq!(Cca::new(70, 1, 1, 1).delay(u32::MAX), 1);
+    }
+
+    #[test]
+    fn cca_settings_are_checked() {
+        let config = |cca| ConfigBuilder::default().cca(Some(cca)).check();
+        assert!(config(Cca::new(70, 4, 500, 10)).is_ok());
+        assert!(config(Cca::new(70, 0, 500, 10)).is_err());
+        assert!(config(Cca::new(70, 4, 0, 10)).is_err());
+        assert!(config(Cca::new(70, 4, 500, 0)).is_err());
+    }
+}
@@ -5,8 +5,38 @@ use bbqueue::{
 };
 use core::ops::{Deref, DerefMut};
 
-// | SW USE                        |               ACTUAL DMA PART                                    |
-// | rssi - 1 byte | pipe - 1 byte | length - 1 byte | pid_no_ack - 1 byte | payload - 1 to 252 bytes |
+// | SW USE                                 |               ACTUAL DMA PART                                    |
+// | rssi or epoch - 1 byte | pipe - 1 byte | length - 1 byte | pid_no_ack - 1 byte | payload - 1 to 252 bytes |
+//
+// Outgoing packets with transmit settings are preceded by an extension:
+//
+// | EXTENSION                                                                                              |
+// | flags - 1 byte | tag - 1 byte | attempts - 1 byte | retransmit delay - 2 bytes | deadline - 4 bytes |
+//
+// The first byte holds the RSSI of received packets and the cancellation epoch of outgoing ones,
+// both are 7 bits wide, its MSb is set when the frame starts with the extension instead.
+
+/// Bit of the first byte of a frame set when the frame starts with the extension
+const FLAG_EXTENDED: u8 = 0b1000_0000;
+
+/// Size of the extension in bytes
+const EXTENSION_SIZE: usize = 9;
+
+/// Bit of the flags field set when the packet has a tag
+const FLAG_TAGGED: u8 = 0b0000_0001;
+
+/// Bit of the flags field set when the packet has a deadline
+const FLAG_DEADLINE: u8 = 0b0000_0010;
+
+/// Bit of the flags field set when the packet is a link probe, the tag field holds the sequence
+/// number of the probe
+const FLAG_PROBE: u8 = 0b0000_0100;
+
+/// Value of the attempts field when the packet uses the attempts of the `Config`
+const DEFAULT_ATTEMPTS: u8 = 0xFF;
+
+/// Value of the retransmit delay field when the packet uses the delay of the `Config`
+const DEFAULT_RETRANSMIT_DELAY: u16 = 0;
 
 /// A builder for an `EsbHeader` structure
 ///
@@ -38,6 +68,10 @@ use core::ops::{Deref, DerefMut};
 /// | no_ack    | true          |
 /// | length    | 0             |
 /// | pipe      | 0             |
+/// | maximum_transmit_attempts | from `Config` |
+/// | retransmit_delay          | from `Config` |
+/// | tag                       | none          |
+/// | deadline                  | none          |
 ///
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct EsbHeaderBuilder(EsbHeader);
@@ -49,6 +83,12 @@ impl Default for EsbHeaderBuilder {
             pid_no_ack: 0,
             length: 0,
             pipe: 0,
+            attempts: None,
+            retransmit_delay: None,
+            tag: None,
+            deadline: None,
+            epoch: 0,
+            probe: None,
         })
     }
 }
@@ -88,6 +128,41 @@ impl EsbHeaderBuilder {
         self
     }
 
+    /// Set the maximum number of transmit attempts of this packet, used by the PTX instead of
+    /// the one of the `Config`. Must be in the range 0..=254.
+    pub fn maximum_transmit_attempts(mut self, attempts: u8) -> Self {
+        self.0.attempts = Some(attempts);
+        self
+    }
+
+    /// Set the retransmit delay of this packet in microseconds, used by the PTX instead of the one
+    /// of the `Config`. Must not be zero, values below the minimum accepted by
+    /// [`ConfigBuilder`](../struct.ConfigBuilder.html) for the active configuration are raised to
+    /// that minimum.
+    pub fn retransmit_delay(mut self, micros: u16) -> Self {
+        self.0.retransmit_delay = Some(micros);
+        self
+    }
+
+    /// Set the tag of this packet, the PTX reports the outcome of tagged packets through
+    /// [`EsbApp::read_tx_report`](../app/struct.EsbApp.html#method.read_tx_report).
+    pub fn tag(mut self, tag: u8) -> Self {
+        self.0.tag = Some(tag);
+        self
+    }
+
+    /// Set the deadline of this packet, in ticks of the clock given to
+    /// [`EsbIrq::set_clock`](../irq/struct.EsbIrq.html#method.set_clock).
+    ///
+    /// The PTX drops the packet instead of transmitting or retransmitting it once the clock is
+    /// past the deadline, the comparison handles the wrap around of the clock as long as the
+    /// deadline is less than `2^31` ticks away. A time-to-live is set by adding it to the current
+    /// time of the clock. The deadline is ignored if no clock was given.
+    pub fn deadline(mut self, deadline: u32) -> Self {
+        self.0.deadline = Some(deadline);
+        self
+    }
+
     /// Finalize the header.
     ///
     /// If the set parameters are out of range, an error will be returned.
@@ -98,7 +173,11 @@ impl EsbHeaderBuilder {
         // This checks if "pid" > 3, where pid_no_ack is pid << 1.
         let bad_pid = self.0.pid_no_ack > 0b0000_0111;
 
-        if bad_length || bad_pid || bad_pipe {
+        // These values are used in the queue to mark that the packet uses the `Config`
+        let bad_attempts = self.0.attempts == Some(DEFAULT_ATTEMPTS);
+        let bad_delay = self.0.retransmit_delay == Some(DEFAULT_RETRANSMIT_DELAY);
+
+        if bad_length || bad_pid || bad_pipe || bad_attempts || bad_delay {
             return Err(Error::InvalidParameters);
         }
 
@@ -144,11 +223,29 @@ pub struct EsbHeader {
     pipe: u8,
     pub(crate) length: u8,
     pid_no_ack: u8,
+    attempts: Option<u8>,
+    retransmit_delay: Option<u16>,
+    tag: Option<u8>,
+    deadline: Option<u32>,
+    epoch: u8,
+    probe: Option<u8>,
 }
 
 /// The "packed" representation of an [`EsbHeader`]
 pub(crate) struct HeaderBytes(pub(crate) [u8; 4]);
 
+/// The "packed" representation of the transmit settings of an [`EsbHeader`]
+pub(crate) struct ExtensionBytes(pub(crate) [u8; EXTENSION_SIZE]);
+
+/// Offset of the header in a frame starting with `first_byte`
+fn header_offset(first_byte: u8) -> usize {
+    if first_byte & FLAG_EXTENDED != 0 {
+        EXTENSION_SIZE
+    } else {
+        0
+    }
+}
+
 impl EsbHeader {
     /// Create a new packet header using a builder pattern
     ///
@@ -178,7 +275,8 @@ impl EsbHeader {
     /// data queuing purposes
     fn into_bytes(self) -> HeaderBytes {
         HeaderBytes([
-            self.rssi,
+            // Outgoing packets carry the cancellation epoch instead of the RSSI
+            self.epoch,
             self.pipe,
             // DO NOT REORDER!
             self.length,
@@ -186,16 +284,96 @@ impl EsbHeader {
         ])
     }
 
+    /// convert the transmit settings into the packed representation of the extension
+    fn extension_bytes(self) -> ExtensionBytes {
+        let mut bytes = [0; EXTENSION_SIZE];
+        bytes[Self::flags_idx()] = FLAG_EXTENDED;
+        if let Some(tag) = self.tag {
+            bytes[Self::flags_idx()] |= FLAG_TAGGED;
+            bytes[Self::tag_idx()] = tag;
+        }
+        if let Some(seq) = self.probe {
+            bytes[Self::flags_idx()] |= FLAG_PROBE;
+            bytes[Self::tag_idx()] = seq;
+        }
+        if let Some(deadline) = self.deadline {
+            bytes[Self::flags_idx()] |= FLAG_DEADLINE;
+            bytes[Self::deadline_idx()..].copy_from_slice(&deadline.to_le_bytes());
+        }
+        bytes[Self::attempts_idx()] = self.attempts.unwrap_or(DEFAULT_ATTEMPTS);
+        bytes[Self::retransmit_delay_idx()..Self::deadline_idx()].copy_from_slice(
+            &self
+                .retransmit_delay
+                .unwrap_or(DEFAULT_RETRANSMIT_DELAY)
+                .to_le_bytes(),
+        );
+        ExtensionBytes(bytes)
+    }
+
     /// convert from a packed representation
-    pub(crate) fn from_bytes(bytes: HeaderBytes) -> Self {
-        Self {
+    pub(crate) fn from_bytes(bytes: HeaderBytes, extension: Option<ExtensionBytes>) -> Self {
+        let mut header = Self {
             rssi: bytes.0[Self::rssi_idx()],
             pipe: bytes.0[Self::pipe_idx()],
             length: bytes.0[Self::length_idx()],
             pid_no_ack: bytes.0[Self::pid_no_ack_idx()],
+            attempts: None,
+            retransmit_delay: None,
+            tag: None,
+            deadline: None,
+            epoch: bytes.0[Self::rssi_idx()],
+            probe: None,
+        };
+        if let Some(ExtensionBytes(bytes)) = extension {
+            let flags = bytes[Self::flags_idx()];
+            header.attempts = Some(bytes[Self::attempts_idx()]).filter(|&a| a != DEFAULT_ATTEMPTS);
+            header.retransmit_delay = Some(u16::from_le_bytes([
+                bytes[Self::retransmit_delay_idx()],
+                bytes[Self::retransmit_delay_idx() + 1],
+            ]))
+            .filter(|&d| d != DEFAULT_RETRANSMIT_DELAY);
+            header.tag = Some(bytes[Self::tag_idx()]).filter(|_| flags & FLAG_TAGGED != 0);
+            header.deadline = Some(u32::from_le_bytes([
+                bytes[Self::deadline_idx()],
+                bytes[Self::deadline_idx() + 1],
+                bytes[Self::deadline_idx() + 2],
+                bytes[Self::deadline_idx() + 3],
+            ]))
+            .filter(|_| flags & FLAG_DEADLINE != 0);
+            header.probe = Some(bytes[Self::tag_idx()]).filter(|_| flags & FLAG_PROBE != 0);
+        }
+        header
+    }
+
+    /// Whether the header has transmit settings, which are kept in the extension
+    fn has_extension(self) -> bool {
+        self.attempts.is_some()
+            || self.retransmit_delay.is_some()
+            || self.tag.is_some()
+            || self.deadline.is_some()
+            || self.probe.is_some()
+    }
+
+    /// Size of the header in the queue, including the extension if needed, in bytes
+    pub(crate) fn queue_size(self) -> usize {
+        if self.has_extension() {
+            EXTENSION_SIZE + Self::header_size()
+        } else {
+            Self::header_size()
         }
     }
 
+    /// Writes the header at the start of `frame`, with or without the extension
+    fn write_to(self, frame: &mut [u8], extended: bool) {
+        let offset = if extended {
+            frame[..EXTENSION_SIZE].copy_from_slice(&self.extension_bytes().0);
+            EXTENSION_SIZE
+        } else {
+            0
+        };
+        frame[offset..offset + Self::header_size()].copy_from_slice(&self.into_bytes().0);
+    }
+
     /// Accessor for the Pipe ID of the packet
     pub fn pid(self) -> u8 {
         self.pid_no_ack >> 1
@@ -216,13 +394,66 @@ impl EsbHeader {
         self.rssi
     }
 
-    /// Byte index of the RSSI field
+    /// Accessor for the pipe number of the packet
+    pub fn pipe(self) -> u8 {
+        self.pipe
+    }
+
+    /// Accessor for the maximum number of transmit attempts of the packet, `None` if it uses the
+    /// one of the `Config`
+    pub fn maximum_transmit_attempts(self) -> Option<u8> {
+        self.attempts
+    }
+
+    /// Accessor for the retransmit delay of the packet, `None` if it uses the one of the `Config`
+    pub fn retransmit_delay(self) -> Option<u16> {
+        self.retransmit_delay
+    }
+
+    /// Cancellation epoch of the pipe when the packet was granted
+    pub(crate) fn epoch(self) -> u8 {
+        self.epoch
+    }
+
+    /// Sets the cancellation epoch
+    pub(crate) fn set_epoch(&mut self, epoch: u8) {
+        self.epoch = epoch;
+    }
+
+    /// Sequence number of the probe, `None` if the packet is not a link probe
+    pub(crate) fn probe(self) -> Option<u8> {
+        self.probe
+    }
+
+    /// Turns the packet into a link probe, a probe can't have a tag
+    pub(crate) fn set_probe(&mut self, seq: u8) {
+        self.tag = None;
+        self.probe = Some(seq);
+    }
+
+    /// Replaces the pid, it must be between 0 and 3
+    pub(crate) fn set_pid(&mut self, pid: u8) {
+        self.pid_no_ack = (self.pid_no_ack & 0b0000_0001) | (pid << 1);
+    }
+
+    /// Accessor for the tag of the packet, `None` if the packet is not tagged
+    pub fn tag(self) -> Option<u8> {
+        self.tag
+    }
+
+    /// Accessor for the deadline of the packet, `None` if the packet has no deadline
+    pub fn deadline(self) -> Option<u32> {
+        self.deadline
+    }
+
+    /// Byte index of the RSSI field, also used for the cancellation epoch
     const fn rssi_idx() -> usize {
         0
     }
 
     /// Byte index of the pipe field
     const fn pipe_idx() -> usize {
+        // DO NOT CHANGE! Must be right before the length field for `ccm_slice`
         1
     }
 
@@ -238,14 +469,39 @@ impl EsbHeader {
         3
     }
 
-    /// Size of the header (packed) in bytes
+    /// Byte index of the flags field in the extension
+    const fn flags_idx() -> usize {
+        0
+    }
+
+    /// Byte index of the tag field in the extension
+    const fn tag_idx() -> usize {
+        1
+    }
+
+    /// Byte index of the attempts field in the extension
+    const fn attempts_idx() -> usize {
+        2
+    }
+
+    /// Byte index of the retransmit delay field in the extension, little endian
+    const fn retransmit_delay_idx() -> usize {
+        3
+    }
+
+    /// Byte index of the deadline field in the extension, little endian
+    const fn deadline_idx() -> usize {
+        5
+    }
+
+    /// Size of the header (packed) in bytes, without the extension
     pub(crate) const fn header_size() -> usize {
         core::mem::size_of::<HeaderBytes>()
     }
 
     /// Offset of the bytes needed for DMA processing
     const fn dma_payload_offset() -> usize {
-        2
+        Self::length_idx()
     }
 }
 
@@ -268,45 +524,76 @@ where
         Self { grant: raw_grant }
     }
 
+    /// Offset of the header, past the extension if the frame has one
+    fn offset(&self) -> usize {
+        header_offset(self.grant[0])
+    }
+
     /// Obtain a copy of the header encoded in the current grant
     pub fn get_header(&self) -> EsbHeader {
         const LEN: usize = EsbHeader::header_size();
+        let offset = self.offset();
         let mut bytes = [0u8; LEN];
-        bytes.copy_from_slice(&self.grant[..LEN]);
-        EsbHeader::from_bytes(HeaderBytes(bytes))
+        bytes.copy_from_slice(&self.grant[offset..offset + LEN]);
+        let extension = if offset > 0 {
+            let mut extension = [0u8; EXTENSION_SIZE];
+            extension.copy_from_slice(&self.grant[..EXTENSION_SIZE]);
+            Some(ExtensionBytes(extension))
+        } else {
+            None
+        };
+        EsbHeader::from_bytes(HeaderBytes(bytes), extension)
     }
 
     /// Obtain a pointer to the data to provide to the RADIO DMA.
     ///
     /// This includes part of the header, as well as the full payload
     pub(crate) fn dma_pointer(&self) -> *const u8 {
-        (&self.grant[EsbHeader::dma_payload_offset()..]).as_ptr()
+        self.grant[self.offset() + EsbHeader::dma_payload_offset()..].as_ptr()
     }
 
     /// Utility method to use with the CCM peripheral present in Nordic's devices. This gives a
     /// slice starting from the pipe field of the header.
     pub fn ccm_slice(&self) -> &[u8] {
-        &self.grant[EsbHeader::pipe_idx()..]
+        &self.grant[self.offset() + EsbHeader::pipe_idx()..]
     }
 
     /// An accessor function for the pipe of the current grant
     pub fn pipe(&self) -> u8 {
-        self.grant[EsbHeader::pipe_idx()]
+        self.grant[self.offset() + EsbHeader::pipe_idx()]
+    }
+
+    /// Cancellation epoch of the current grant
+    pub(crate) fn epoch(&self) -> u8 {
+        self.grant[self.offset() + EsbHeader::rssi_idx()]
     }
 
     /// An accessor function to get the pipe id of the current grant
     pub fn pid(&self) -> u8 {
-        self.grant[EsbHeader::pid_no_ack_idx()] >> 1
+        self.grant[self.offset() + EsbHeader::pid_no_ack_idx()] >> 1
     }
 
     /// An accessor function for the no-ack field of the current grant
     pub fn no_ack(&self) -> bool {
-        self.grant[EsbHeader::pid_no_ack_idx()] & 1 != 1
+        self.grant[self.offset() + EsbHeader::pid_no_ack_idx()] & 1 != 1
     }
 
     /// An accessor function to get the size of the payload of the current grant
     pub fn payload_len(&self) -> usize {
-        self.grant[EsbHeader::length_idx()] as usize
+        // The committed frame always holds the header and the whole payload, this is also valid
+        // after the length field was replaced by `pack_static_control_field`
+        self.grant.len() - self.offset() - EsbHeader::header_size()
+    }
+
+    /// Rewrites the packet control field to the on-air layout of the static payload length mode.
+    ///
+    /// In this mode the radio uses a S0 byte holding the (unused) length and the PID, followed by a
+    /// S1 byte holding the no-ack bit, the length field is overwritten by the S0 byte. This
+    /// conversion can be applied more than once.
+    pub(crate) fn pack_static_control_field(&mut self) {
+        let offset = self.offset();
+        self.grant[offset + EsbHeader::length_idx()] =
+            self.grant[offset + EsbHeader::pid_no_ack_idx()] >> 1;
     }
 
     /// This function marks the packet as read, and restores the space
@@ -334,7 +621,7 @@ where
 
     /// Provide read only access to the payload of a grant
     fn deref(&self) -> &Self::Target {
-        &self.grant[EsbHeader::header_size()..]
+        &self.grant[self.offset() + EsbHeader::header_size()..]
     }
 }
 
@@ -344,12 +631,87 @@ where
 {
     /// provide read/write access to the payload portion of the grant
     fn deref_mut(&mut self) -> &mut [u8] {
-        &mut self.grant[EsbHeader::header_size()..]
+        let offset = self.offset();
+        &mut self.grant[offset + EsbHeader::header_size()..]
+    }
+}
+
+/// An outgoing packet taken by the radio, from the normal or from the priority queue
+pub(crate) enum TxPayload<OutgoingLen, PriorityLen>
+where
+    OutgoingLen: ArrayLength<u8>,
+    PriorityLen: ArrayLength<u8>,
+{
+    Normal(PayloadR<OutgoingLen>),
+    Priority(PayloadR<PriorityLen>),
+}
+
+impl<OutgoingLen, PriorityLen> TxPayload<OutgoingLen, PriorityLen>
+where
+    OutgoingLen: ArrayLength<u8>,
+    PriorityLen: ArrayLength<u8>,
+{
+    /// See [`PayloadR::get_header`]
+    pub(crate) fn get_header(&self) -> EsbHeader {
+        match self {
+            Self::Normal(payload) => payload.get_header(),
+            Self::Priority(payload) => payload.get_header(),
+        }
+    }
+
+    /// See [`PayloadR::dma_pointer`]
+    pub(crate) fn dma_pointer(&self) -> *const u8 {
+        match self {
+            Self::Normal(payload) => payload.dma_pointer(),
+            Self::Priority(payload) => payload.dma_pointer(),
+        }
+    }
+
+    /// See [`PayloadR::pipe`]
+    pub(crate) fn pipe(&self) -> u8 {
+        match self {
+            Self::Normal(payload) => payload.pipe(),
+            Self::Priority(payload) => payload.pipe(),
+        }
+    }
+
+    /// See [`PayloadR::epoch`]
+    pub(crate) fn epoch(&self) -> u8 {
+        match self {
+            Self::Normal(payload) => payload.epoch(),
+            Self::Priority(payload) => payload.epoch(),
+        }
+    }
+
+    /// See [`PayloadR::no_ack`]
+    pub(crate) fn no_ack(&self) -> bool {
+        match self {
+            Self::Normal(payload) => payload.no_ack(),
+            Self::Priority(payload) => payload.no_ack(),
+        }
+    }
+
+    /// See [`PayloadR::pack_static_control_field`]
+    pub(crate) fn pack_static_control_field(&mut self) {
+        match self {
+            Self::Normal(payload) => payload.pack_static_control_field(),
+            Self::Priority(payload) => payload.pack_static_control_field(),
+        }
+    }
+
+    /// See [`PayloadR::release`]
+    pub(crate) fn release(self) {
+        match self {
+            Self::Normal(payload) => payload.release(),
+            Self::Priority(payload) => payload.release(),
+        }
     }
 }
 
 pub struct PayloadW<N: ArrayLength<u8>> {
     grant: FrameGrantW<'static, N>,
+    // The payload length can't be changed, as in the static payload length mode
+    fixed_length: bool,
 }
 
 impl<N> PayloadW<N>
@@ -366,7 +728,12 @@ where
     /// The `length` of the packet can not be increased, only shrunk. If a larger
     /// payload is needed, you must drop the current payload grant, and obtain a new
     /// one. If the new header has a larger `length` than the current `length`, then
-    /// it will be truncated.
+    /// it will be truncated. In the static payload length mode, the `length` can not be changed at
+    /// all and the `length` of the new header is ignored.
+    ///
+    /// The transmit settings (maximum transmit attempts, retransmit delay and tag) are only kept in
+    /// the queue for packets whose grant was obtained with at least one of them, they are ignored
+    /// otherwise.
     pub fn update_header(&mut self, mut header: EsbHeader) {
         // TODO(AJM): Technically, we could drop the current grant, and request a larger one
         // here, and it would totally work. However for now, let's just truncate, because growing
@@ -374,9 +741,24 @@ where
 
         // `length` must always be 0..=252 (checked by constructor), so `u8` cast is
         // appropriate here
-        let payload_max = self.grant.len().saturating_sub(EsbHeader::header_size());
-        header.length = header.length.min(payload_max as u8);
-        self.grant[..EsbHeader::header_size()].copy_from_slice(&header.into_bytes().0);
+        let offset = self.offset();
+        let payload_max = self
+            .grant
+            .len()
+            .saturating_sub(offset + EsbHeader::header_size());
+        header.length = if self.fixed_length {
+            payload_max as u8
+        } else {
+            header.length.min(payload_max as u8)
+        };
+        // The cancellation epoch was set when the packet was granted
+        header.epoch = self.grant[offset + EsbHeader::rssi_idx()];
+        header.write_to(&mut self.grant, offset > 0);
+    }
+
+    /// Offset of the header, past the extension if the frame has one
+    fn offset(&self) -> usize {
+        header_offset(self.grant[0])
     }
 
     /// Utility method to use with the CCM peripheral present in Nordic's devices. This gives a
@@ -399,26 +781,42 @@ where
     /// contain. When using this slice to store the output of the CCM operation, the CCM peripheral
     /// will modify this field, the user must ensure that this field remains in a valid range.
     pub unsafe fn ccm_slice(&mut self) -> &mut [u8] {
-        &mut self.grant[EsbHeader::pipe_idx()..]
+        let offset = self.offset();
+        &mut self.grant[offset + EsbHeader::pipe_idx()..]
     }
 
     /// Obtain a writable grant from the application side.
     ///
-    /// This method should only be used from within `EsbApp`.
-    pub(crate) fn new_from_app(mut raw_grant: FrameGrantW<'static, N>, header: EsbHeader) -> Self {
-        raw_grant[..EsbHeader::header_size()].copy_from_slice(&header.into_bytes().0);
-        Self { grant: raw_grant }
+    /// This method should only be used from within `EsbApp`, `fixed_length` must be set in the
+    /// static payload length mode.
+    pub(crate) fn new_from_app(
+        mut raw_grant: FrameGrantW<'static, N>,
+        header: EsbHeader,
+        fixed_length: bool,
+    ) -> Self {
+        header.write_to(&mut raw_grant, header.has_extension());
+        Self {
+            grant: raw_grant,
+            fixed_length,
+        }
     }
 
     /// Obtain a writable grant from the RADIO/interrupt side.
     ///
     /// This method should only be used from within `EsbIrq`.
-    pub(crate) fn new_from_radio(raw_grant: FrameGrantW<'static, N>) -> Self {
-        Self { grant: raw_grant }
+    pub(crate) fn new_from_radio(mut raw_grant: FrameGrantW<'static, N>) -> Self {
+        // Received packets never have the extension, the RSSI is written once the packet is
+        // checked
+        raw_grant[EsbHeader::rssi_idx()] = 0;
+        Self {
+            grant: raw_grant,
+            fixed_length: false,
+        }
     }
 
+    // Only used for grants of the radio, which don't have the extension
     pub(crate) fn dma_pointer(&mut self) -> *mut u8 {
-        (&mut self.grant[EsbHeader::dma_payload_offset()..]).as_mut_ptr()
+        self.grant[EsbHeader::dma_payload_offset()..].as_mut_ptr()
     }
 
     /// Update the pipe field.
@@ -435,24 +833,42 @@ where
         self.grant[EsbHeader::rssi_idx()] = rssi;
     }
 
+    /// Converts a packet received in the static payload length mode back to the in-queue
+    /// representation, see [`PayloadR::pack_static_control_field`].
+    #[inline]
+    pub(crate) fn unpack_static_control_field(&mut self, length: u8) {
+        let pid = self.grant[EsbHeader::length_idx()] & 0b0000_0011;
+        let ack = self.grant[EsbHeader::pid_no_ack_idx()] & 0b0000_0001;
+        self.grant[EsbHeader::pid_no_ack_idx()] = (pid << 1) | ack;
+        self.grant[EsbHeader::length_idx()] = length;
+    }
+
+    /// Fills the header of a packet received in the legacy ShockBurst mode, these packets don't
+    /// have a packet control field, so the DMA didn't touch the header.
+    #[inline]
+    pub(crate) fn unpack_legacy_control_field(&mut self, length: u8) {
+        self.grant[EsbHeader::pid_no_ack_idx()] = 0;
+        self.grant[EsbHeader::length_idx()] = length;
+    }
+
     /// An accessor function to get the pipe id of the current grant
     pub fn pipe(&self) -> u8 {
-        self.grant[EsbHeader::pipe_idx()]
+        self.grant[self.offset() + EsbHeader::pipe_idx()]
     }
 
     /// An accessor function to get the pipe id of the current grant
     pub fn pid(&self) -> u8 {
-        self.grant[EsbHeader::pid_no_ack_idx()] >> 1
+        self.grant[self.offset() + EsbHeader::pid_no_ack_idx()] >> 1
     }
 
     /// An accessor function for the no-ack field of the current grant
     pub fn no_ack(&self) -> bool {
-        self.grant[EsbHeader::pid_no_ack_idx()] & 1 != 1
+        self.grant[self.offset() + EsbHeader::pid_no_ack_idx()] & 1 != 1
     }
 
     /// An accessor function to get the maximum size of the payload of the current grant
     pub fn payload_len(&self) -> usize {
-        self.grant[EsbHeader::length_idx()] as usize
+        self.grant[self.offset() + EsbHeader::length_idx()] as usize
     }
 
     /// Commit the entire granted packet and payload
@@ -462,19 +878,31 @@ where
     /// sent.
     pub fn commit_all(self) {
         let payload_len = self.payload_len();
-        self.grant.commit(payload_len + EsbHeader::header_size())
+        let offset = self.offset();
+        self.grant
+            .commit(payload_len + offset + EsbHeader::header_size())
     }
 
     /// Set the amount to automatically commit on drop
     ///
     /// If `None` is given, then the packet will not be commited. If `Some(0)`
-    /// is given, then an empty packet will be committed automatically
+    /// is given, then an empty packet will be committed automatically. In the static payload
+    /// length mode, the whole payload is committed whatever the amount given.
     pub fn to_commit(&mut self, amt: Option<usize>) {
         if let Some(amt) = amt {
-            let payload_max = self.grant.len().saturating_sub(EsbHeader::header_size());
-            let payload_len = payload_max.min(amt);
-            self.grant[EsbHeader::length_idx()] = payload_len as u8;
-            self.grant.to_commit(payload_len + EsbHeader::header_size());
+            let offset = self.offset();
+            let payload_max = self
+                .grant
+                .len()
+                .saturating_sub(offset + EsbHeader::header_size());
+            let payload_len = if self.fixed_length {
+                payload_max
+            } else {
+                payload_max.min(amt)
+            };
+            self.grant[offset + EsbHeader::length_idx()] = payload_len as u8;
+            self.grant
+                .to_commit(payload_len + offset + EsbHeader::header_size());
         } else {
             self.grant.to_commit(0);
         }
@@ -487,12 +915,19 @@ where
     /// sent.
     ///
     /// If `used` is larger than the maximum size of the grant (or of the
-    /// ESB protocol), the packet will be truncated.
+    /// ESB protocol), the packet will be truncated. In the static payload
+    /// length mode, the whole payload is committed whatever `used` is.
     pub fn commit(mut self, used: usize) {
-        let payload_len = self.payload_len().min(used);
-        self.grant[EsbHeader::length_idx()] = payload_len as u8;
+        let payload_len = if self.fixed_length {
+            self.payload_len()
+        } else {
+            self.payload_len().min(used)
+        };
+        let offset = self.offset();
+        self.grant[offset + EsbHeader::length_idx()] = payload_len as u8;
 
-        self.grant.commit(payload_len + EsbHeader::header_size())
+        self.grant
+            .commit(payload_len + offset + EsbHeader::header_size())
     }
 }
 
@@ -504,7 +939,7 @@ where
 
     /// provide read only access to the payload portion of the grant
     fn deref(&self) -> &Self::Target {
-        &self.grant[EsbHeader::header_size()..]
+        &self.grant[self.offset() + EsbHeader::header_size()..]
     }
 }
 
@@ -514,6 +949,129 @@ where
 {
     /// provide read/write access to the payload portion of the grant
     fn deref_mut(&mut self) -> &mut [u8] {
-        &mut self.grant[EsbHeader::header_size()..]
+        let offset = self.offset();
+        &mut self.grant[offset + EsbHeader::header_size()..]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bbqueue::{consts::U64, BBBuffer, ConstBBBuffer};
+
+    #[test]
+    fn static_control_field_round_trip() {
+        static TX_BUFFER: BBBuffer<U64> = BBBuffer(ConstBBBuffer::new());
+        static RX_BUFFER: BBBuffer<U64> = BBBuffer(ConstBBBuffer::new());
+        let (mut tx_prod, mut tx_cons) = TX_BUFFER.try_split_framed().unwrap();
+        let (mut rx_prod, mut rx_cons) = RX_BUFFER.try_split_framed().unwrap();
+
+        let header = EsbHeader::new(4, 2, 5, false).unwrap();
+        let grant = tx_prod.grant(EsbHeader::header_size() + 4).unwrap();
+        let mut packet = PayloadW::new_from_app(grant, header, false);
+        packet.copy_from_slice(&[1, 2, 3, 4]);
+        packet.commit_all();
+
+        let mut packet = PayloadR::new(tx_cons.read().unwrap());
+        packet.pack_static_control_field();
+        // Packing twice, as for retransmissions, doesn't change the packet
+        packet.pack_static_control_field();
+        assert_eq!(packet.payload_len(), 4);
+        assert_eq!(packet.pid(), 2);
+        assert!(!packet.no_ack());
+
+        // The receiver gets the same bytes from the DMA
+        let mut grant = rx_prod.grant(EsbHeader::header_size() + 4).unwrap();
+        let offset = EsbHeader::dma_payload_offset();
+        grant[offset..].copy_from_slice(&packet.grant[offset..]);
+        let mut received = PayloadW::new_from_radio(grant);
+        received.unpack_static_control_field(4);
+        received.set_pipe(5);
+        assert_eq!(received.pid(), 2);
+        assert!(!received.no_ack());
+        assert_eq!(received.payload_len(), 4);
+        received.commit_all();
+
+        let received = PayloadR::new(rx_cons.read().unwrap());
+        let header = received.get_header();
+        assert_eq!((header.pid(), header.no_ack()), (2, false));
+        assert_eq!(&received[..], &[1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn unpack_legacy_control_field() {
+        static BUFFER: BBBuffer<U64> = BBBuffer(ConstBBBuffer::new());
+        let (mut prod, mut cons) = BUFFER.try_split_framed().unwrap();
+
+        let mut grant = prod.grant(EsbHeader::header_size() + 4).unwrap();
+        // The DMA doesn't write the packet control field of legacy packets
+        grant[EsbHeader::length_idx()] = 0xAA;
+        grant[EsbHeader::pid_no_ack_idx()] = 0x55;
+        let mut packet = PayloadW::new_from_radio(grant);
+        packet.copy_from_slice(&[1, 2, 3, 4]);
+        packet.unpack_legacy_control_field(4);
+        packet.set_pipe(3);
+        assert_eq!(packet.pid(), 0);
+        assert!(packet.no_ack());
+        assert_eq!(packet.payload_len(), 4);
+        packet.commit_all();
+
+        let packet = PayloadR::new(cons.read().unwrap());
+        let header = packet.get_header();
+        assert_eq!(header.pid(), 0);
+        assert!(header.no_ack());
+        assert_eq!(packet.pipe(), 3);
+        assert_eq!(&packet[..], &[1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn transmit_settings_use_the_extension() {
+        static BUFFER: BBBuffer<U64> = BBBuffer(ConstBBBuffer::new());
+        let (mut prod, mut cons) = BUFFER.try_split_framed().unwrap();
+
+        let plain = EsbHeader::new(4, 1, 2, false).unwrap();
+        let extended = EsbHeader::build()
+            .max_payload(4)
+            .pid(1)
+            .pipe(2)
+            .no_ack(false)
+            .maximum_transmit_attempts(5)
+            .retransmit_delay(1000)
+            .tag(9)
+            .deadline(0x1234_5678)
+            .check()
+            .unwrap();
+        assert_eq!(plain.queue_size(), EsbHeader::header_size());
+        assert_eq!(
+            extended.queue_size(),
+            EsbHeader::header_size() + EXTENSION_SIZE
+        );
+
+        for &(mut header) in [plain, extended].iter() {
+            // The largest epoch must not be taken for the extension flag
+            header.set_epoch(0x7F);
+            let grant = prod.grant(header.queue_size() + 4).unwrap();
+            let mut packet = PayloadW::new_from_app(grant, header, false);
+            assert_eq!(packet.pipe(), 2);
+            assert_eq!(packet.payload_len(), 4);
+            packet.copy_from_slice(&[1, 2, 3, 4]);
+            packet.commit(3);
+
+            let packet = PayloadR::new(cons.read().unwrap());
+            let read = packet.get_header();
+            assert_eq!(read.pid(), 1);
+            assert!(!read.no_ack());
+            assert_eq!(
+                read.maximum_transmit_attempts(),
+                header.maximum_transmit_attempts()
+            );
+            assert_eq!(read.retransmit_delay(), header.retransmit_delay());
+            assert_eq!(read.tag(), header.tag());
+            assert_eq!(read.deadline(), header.deadline());
+            assert_eq!(read.epoch(), 0x7F);
+            assert_eq!(packet.payload_len(), 3);
+            assert_eq!(&packet[..], &[1, 2, 3]);
+            packet.release();
+        }
     }
 }
@@ -13,18 +13,24 @@ use nrf52833_pac as pac;
 #[cfg(feature = "52840")]
 use nrf52840_pac as pac;
 
-use bbqueue::{framed::FrameConsumer, ArrayLength};
+use bbqueue::{consts::U0, framed::FrameConsumer, ArrayLength};
 use core::sync::atomic::{compiler_fence, Ordering};
 
+#[cfg(not(feature = "51"))]
+use crate::RampUp;
 use crate::{
     app::Addresses,
-    payload::{PayloadR, PayloadW},
-    Error,
+    cancel::TxCancel,
+    payload::{EsbHeader, PayloadR, PayloadW, TxPayload},
+    random::RandomSource,
+    Config, Crc, DataRate, Error, PayloadLength, Protocol,
 };
 pub(crate) use pac::{radio::txpower::TXPOWER_A, Interrupt, NVIC, RADIO};
 
-const CRC_INIT: u32 = 0x0000_FFFF;
-const CRC_POLY: u32 = 0x0001_1021;
+const CRC8_INIT: u32 = 0x0000_00FF;
+const CRC8_POLY: u32 = 0x0000_0107;
+const CRC16_INIT: u32 = 0x0000_FFFF;
+const CRC16_POLY: u32 = 0x0001_1021;
 const NUM_PIPES: usize = 8;
 
 #[inline]
@@ -37,6 +43,15 @@ fn address_conversion(value: u32) -> u32 {
     value.reverse_bits()
 }
 
+// Initial value and polynomial of the CRC, as written to the CRCINIT and CRCPOLY registers
+fn crc_parameters(crc: Crc) -> (u32, u32) {
+    match crc {
+        Crc::Disabled => (0, 0),
+        Crc::OneByte => (CRC8_INIT, CRC8_POLY),
+        Crc::TwoBytes => (CRC16_INIT, CRC16_POLY),
+    }
+}
+
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub(crate) enum RxPayloadState {
     Ack,
@@ -44,44 +59,102 @@ pub(crate) enum RxPayloadState {
     RepeatedAck,
     RepeatedNoAck,
     BadCRC,
+    /// The packet doesn't fit the settings of its pipe
+    Rejected,
 }
 
-pub struct EsbRadio<OutgoingLen, IncomingLen>
+// Progress of a clear channel assessment
+#[derive(Copy, Clone)]
+struct Assessment {
+    remaining: u8,
+    minimum: u8,
+}
+
+pub struct EsbRadio<OutgoingLen, IncomingLen, PriorityLen = U0>
 where
     OutgoingLen: ArrayLength<u8>,
     IncomingLen: ArrayLength<u8>,
+    PriorityLen: ArrayLength<u8>,
 {
     radio: RADIO,
-    tx_grant: Option<PayloadR<OutgoingLen>>,
+    tx_grant: Option<TxPayload<OutgoingLen, PriorityLen>>,
     rx_grant: Option<PayloadW<IncomingLen>>,
+    poll_frame: [u8; 2],
+    poll_pipe: Option<u8>,
+    assessment: Option<Assessment>,
     last_crc: [u16; NUM_PIPES],
     last_pid: [u8; NUM_PIPES],
     cached_pipe: u8,
+    crc: Crc,
+    static_length: Option<u8>,
+    protocol: Protocol,
+    auto_ack: [bool; NUM_PIPES],
+    pipe_maximum_payload: [u8; NUM_PIPES],
 }
 
-impl<OutgoingLen, IncomingLen> EsbRadio<OutgoingLen, IncomingLen>
+impl<OutgoingLen, IncomingLen, PriorityLen> EsbRadio<OutgoingLen, IncomingLen, PriorityLen>
 where
     OutgoingLen: ArrayLength<u8>,
     IncomingLen: ArrayLength<u8>,
+    PriorityLen: ArrayLength<u8>,
 {
     pub(crate) fn new(radio: RADIO) -> Self {
         EsbRadio {
             radio,
             tx_grant: None,
             rx_grant: None,
+            poll_frame: [0; 2],
+            poll_pipe: None,
+            assessment: None,
             last_crc: [0; NUM_PIPES],
-            last_pid: [0; NUM_PIPES],
+            // Invalid PID, so the first packet of each pipe is never considered a repeated one
+            last_pid: [0xFF; NUM_PIPES],
             cached_pipe: 0,
+            crc: Crc::TwoBytes,
+            static_length: None,
+            protocol: Protocol::EnhancedShockBurst,
+            auto_ack: [true; NUM_PIPES],
+            pipe_maximum_payload: [0; NUM_PIPES],
         }
     }
 
-    pub(crate) fn init(&mut self, max_payload: u8, tx_power: TXPOWER_A, addresses: &Addresses) {
+    pub(crate) fn init(&mut self, config: &Config, addresses: &Addresses) {
+        let max_payload = config.maximum_payload_size;
         // Disables all interrupts, Nordic's code writes to all bits, seems to be okay
         self.radio
             .intenclr
             .write(|w| unsafe { w.bits(0xFFFF_FFFF) });
-        self.radio.mode.write(|w| w.mode().nrf_2mbit());
-        let len_bits = if max_payload <= 32 { 6 } else { 8 };
+        self.radio.mode.write(|w| match config.data_rate {
+            DataRate::Nrf1Mbit => w.mode().nrf_1mbit(),
+            DataRate::Nrf2Mbit => w.mode().nrf_2mbit(),
+            #[cfg(feature = "51")]
+            DataRate::Nrf250Kbit => w.mode().nrf_250kbit(),
+            DataRate::Ble1Mbit => w.mode().ble_1mbit(),
+            #[cfg(not(feature = "51"))]
+            DataRate::Ble2Mbit => w.mode().ble_2mbit(),
+        });
+        // In the static payload length mode, the 9-bit packet control field is split into a S0
+        // byte containing the unused 6-bit length and the PID, and a 1-bit S1 field containing
+        // the no-ack bit. The legacy ShockBurst mode doesn't have a packet control field at all
+        let (len_bits, s0_len, s1_len, static_length) =
+            match (config.protocol, config.payload_length) {
+                (Protocol::ShockBurst, PayloadLength::Static(length)) => {
+                    (0, false, 0, Some(length))
+                }
+                (_, PayloadLength::Static(length)) => (0, true, 1, Some(length)),
+                (_, PayloadLength::Dynamic) => {
+                    (if max_payload <= 32 { 6 } else { 8 }, false, 3, None)
+                }
+            };
+        self.static_length = static_length;
+        self.protocol = config.protocol;
+        for (auto_ack, pipe) in self.auto_ack.iter_mut().zip(config.pipes().iter()) {
+            *auto_ack = pipe.auto_ack();
+        }
+        self.pipe_maximum_payload = config.pipe_maximum_payload_sizes();
+        // The pipes might have new addresses, forget the last received packets
+        self.last_crc = [0; NUM_PIPES];
+        self.last_pid = [0xFF; NUM_PIPES];
         // Convert addresses to remain compatible with nRF24L devices
         let base0 = address_conversion(u32::from_le_bytes(addresses.base0));
         let base1 = address_conversion(u32::from_le_bytes(addresses.base1));
@@ -99,40 +172,56 @@ where
                 .enabled()
         });
 
-        // Enable fast ramp-up
-        #[cfg(feature = "fast-ru")]
-        self.radio.modecnf0.modify(|_, w| w.ru().fast());
+        // nRF51 devices don't support the fast ramp-up
+        #[cfg(not(feature = "51"))]
+        self.radio.modecnf0.modify(|_, w| match config.ramp_up() {
+            RampUp::Default => w.ru().default(),
+            RampUp::Fast => w.ru().fast(),
+        });
 
-        self.radio.txpower.write(|w| w.txpower().variant(tx_power));
+        self.radio
+            .txpower
+            .write(|w| w.txpower().variant(config.tx_power));
         unsafe {
-            self.radio
-                .pcnf0
-                .write(|w| w.lflen().bits(len_bits).s1len().bits(3));
+            self.radio.pcnf0.write(|w| {
+                w.lflen()
+                    .bits(len_bits)
+                    .s0len()
+                    .bit(s0_len)
+                    .s1len()
+                    .bits(s1_len)
+            });
 
             self.radio.pcnf1.write(|w| {
                 w.maxlen()
                     .bits(max_payload)
-                    // 4-Byte Base Address + 1-Byte Address Prefix
+                    // 2 to 4-Byte Base Address + 1-Byte Address Prefix
                     .balen()
-                    .bits(4)
+                    .bits(addresses.address_width.base_len())
                     // Nordic's code doesn't use whitening, maybe enable in the future ?
                     //.whiteen()
                     //.set_bit()
                     .statlen()
-                    .bits(0)
+                    .bits(static_length.unwrap_or(0))
                     .endian()
                     .big()
             });
 
+            let (crc_init, crc_poly) = crc_parameters(config.crc);
             self.radio
                 .crcinit
-                .write(|w| w.crcinit().bits(CRC_INIT & 0x00FF_FFFF));
+                .write(|w| w.crcinit().bits(crc_init & 0x00FF_FFFF));
 
             self.radio
                 .crcpoly
-                .write(|w| w.crcpoly().bits(CRC_POLY & 0x00FF_FFFF));
+                .write(|w| w.crcpoly().bits(crc_poly & 0x00FF_FFFF));
 
-            self.radio.crccnf.write(|w| w.len().two());
+            self.radio.crccnf.write(|w| match config.crc {
+                Crc::Disabled => w.len().disabled(),
+                Crc::OneByte => w.len().one(),
+                Crc::TwoBytes => w.len().two(),
+            });
+            self.crc = config.crc;
 
             self.radio.base0.write(|w| w.bits(base0));
             self.radio.base1.write(|w| w.bits(base1));
@@ -148,6 +237,15 @@ where
         }
     }
 
+    // Changes the RF channel, the radio must be disabled and the channel must be between 0 and 100
+    #[inline]
+    pub(crate) fn set_channel(&mut self, channel: u8) {
+        // NOTE(unsafe) The caller checked that `channel` is between 0 and 100
+        self.radio
+            .frequency
+            .write(|w| unsafe { w.frequency().bits(channel) });
+    }
+
     // Clears the Disabled event to not retrigger the interrupt
     #[inline]
     pub(crate) fn clear_disabled_event(&mut self) {
@@ -166,6 +264,42 @@ where
         self.radio.events_end.reset();
     }
 
+    // Checks the CRC of the last received packet, always succeeds if the CRC is disabled
+    #[inline]
+    fn crc_ok(&self) -> bool {
+        self.crc == Crc::Disabled || self.radio.crcstatus.read().crcstatus().is_crcok()
+    }
+
+    // Returns the CRC of the last received packet, masked to the configured CRC length
+    #[inline]
+    fn received_crc(&self) -> u16 {
+        match self.crc {
+            // Without a CRC only the PID is used to detect repeated packets
+            Crc::Disabled => 0,
+            Crc::OneByte => self.radio.rxcrc.read().rxcrc().bits() as u16 & 0x00FF,
+            Crc::TwoBytes => self.radio.rxcrc.read().rxcrc().bits() as u16,
+        }
+    }
+
+    // Offset between the DMA pointer of a packet and the data the radio sends or receives, the
+    // legacy ShockBurst mode skips the length and pid_no_ack bytes
+    #[inline]
+    fn dma_offset(&self) -> usize {
+        match self.protocol {
+            Protocol::EnhancedShockBurst => 0,
+            Protocol::ShockBurst => 2,
+        }
+    }
+
+    // Updates the static length, used to send and receive acknowledgements without payload in
+    // the static payload length mode
+    #[inline]
+    fn set_static_length(&mut self, length: u8) {
+        self.radio
+            .pcnf1
+            .modify(|_, w| unsafe { w.statlen().bits(length) });
+    }
+
     // Checks the Ready event
     #[inline]
     pub(crate) fn check_ready_event(&self) -> bool {
@@ -190,6 +324,11 @@ where
             .shorts
             .modify(|_, w| w.disabled_rxen().disabled().disabled_txen().disabled());
         self.disable_disabled_interrupt();
+        // Abandons the clear channel assessment, if any
+        self.radio
+            .intenclr
+            .write(|w| w.ready().set_bit().rssiend().set_bit());
+        self.assessment = None;
         self.radio.tasks_disable.write(|w| unsafe { w.bits(1) });
 
         // Wait for the disable event to kick in, to make sure that the `task_disable` write won't
@@ -204,31 +343,66 @@ where
             // Drop grants we might have
             self.tx_grant.take();
             self.rx_grant.take();
+            self.poll_pipe.take();
         }
     }
 
+    // Pipe of the last new packet received by the PRX
+    pub(crate) fn last_rx_pipe(&self) -> u8 {
+        self.cached_pipe
+    }
+
     // --------------- PTX methods --------------- //
 
+    // Header of the packet being transmitted, if any
+    pub(crate) fn tx_header(&self) -> Option<EsbHeader> {
+        self.tx_grant.as_ref().map(TxPayload::get_header)
+    }
+
     // Transmit a packet and setup interrupts.
-    pub(crate) fn transmit(&mut self, payload: PayloadR<OutgoingLen>, ack: bool) {
+    pub(crate) fn transmit(&mut self, mut payload: TxPayload<OutgoingLen, PriorityLen>, ack: bool) {
+        if self.static_length.is_some() {
+            if self.protocol == Protocol::EnhancedShockBurst {
+                payload.pack_static_control_field();
+            }
+            // The static length of the pipe, which the application checked when granting
+            let length = self.pipe_maximum_payload[usize::from(payload.pipe())];
+            self.set_static_length(length);
+        }
         if ack {
             // Go to RX mode after the transmission
             self.radio.shorts.modify(|_, w| w.disabled_rxen().enabled());
         }
+        let offset = self.dma_offset();
+        self.start_tx(
+            payload.pipe(),
+            payload.dma_pointer().wrapping_add(offset) as u32,
+        );
+        self.tx_grant = Some(payload);
+    }
+
+    // Transmit an empty packet that requests an acknowledgement
+    pub(crate) fn transmit_poll(&mut self, pipe: u8, pid: u8) {
+        // Polls don't have a payload, this is the DMA part of the packet, the no-ack bit is
+        // always cleared
+        self.poll_frame = [0, (pid << 1) | 0b1];
+
+        // Go to RX mode after the transmission
+        self.radio.shorts.modify(|_, w| w.disabled_rxen().enabled());
+        self.start_tx(pipe, self.poll_frame.as_ptr() as u32);
+        self.poll_pipe = Some(pipe);
+    }
+
+    // Setup the interrupts and addresses and start the transmission of the packet at `pointer`
+    fn start_tx(&mut self, pipe: u8, pointer: u32) {
         self.radio.intenset.write(|w| w.disabled().set_bit());
         unsafe {
             // NOTE(unsafe) Pipe fits in 3 bits
-            self.radio
-                .txaddress
-                .write(|w| w.txaddress().bits(payload.pipe()));
+            self.radio.txaddress.write(|w| w.txaddress().bits(pipe));
             // NOTE(unsafe) Pipe only goes from 0 through 7
-            self.radio
-                .rxaddresses
-                .write(|w| w.bits(1 << payload.pipe()));
+            self.radio.rxaddresses.write(|w| w.bits(1 << pipe));
 
-            self.radio
-                .packetptr
-                .write(|w| w.bits(payload.dma_pointer() as u32));
+            self.radio.packetptr.write(|w| w.bits(pointer));
             self.radio.events_address.write(|w| w.bits(0));
             self.clear_disabled_event();
             self.clear_ready_event();
@@ -240,7 +414,6 @@ where
 
             self.radio.tasks_txen.write(|w| w.bits(1));
         }
-        self.tx_grant = Some(payload);
     }
 
     // Must be called after the end of TX if the user did not request an ack
@@ -269,6 +442,10 @@ where
         self.radio
             .packetptr
             .write(|w| unsafe { w.bits(rx_buf.dma_pointer() as u32) });
+        if self.static_length.is_some() {
+            // Acknowledgements don't have a payload in the static payload length mode
+            self.set_static_length(0);
+        }
 
         // Check if the radio turn around was faster than us
         debug_assert!(!self.check_ready_event(), "Missed window (PTX)");
@@ -282,34 +459,135 @@ where
         // We don't release the packet here because we may need to retransmit
     }
 
-    // Returns true if the ack was received successfully
+    // Returns the payload length of the ack if it was received successfully, the empty acks of
+    // polls are not passed to the application
     // The upper stack is responsible for checking and disabling the timeouts
     #[inline]
-    pub(crate) fn check_ack(&mut self) -> Result<bool, Error> {
-        let ret = self.radio.crcstatus.read().crcstatus().is_crcok();
+    pub(crate) fn check_ack(&mut self) -> Result<Option<usize>, Error> {
+        let ret = self.crc_ok();
         // "Subsequent reads and writes cannot be moved ahead of preceding reads."
         compiler_fence(Ordering::Acquire);
 
         if ret {
-            let (tx_grant, mut rx_grant) = (
-                self.tx_grant.take().ok_or(Error::InternalError)?,
-                self.rx_grant.take().ok_or(Error::InternalError)?,
-            );
-
-            let pipe = tx_grant.pipe();
-            tx_grant.release();
+            let mut rx_grant = self.rx_grant.take().ok_or(Error::InternalError)?;
+            let poll_pipe = self.poll_pipe.take();
+            let pipe = match (self.tx_grant.take(), poll_pipe) {
+                (Some(tx_grant), _) => {
+                    let pipe = tx_grant.pipe();
+                    tx_grant.release();
+                    pipe
+                }
+                (None, Some(pipe)) => pipe,
+                (None, None) => return Err(Error::InternalError),
+            };
 
             let rssi = self.radio.rssisample.read().rssisample().bits();
+            if self.static_length.is_some() {
+                rx_grant.unpack_static_control_field(0);
+            }
             rx_grant.set_pipe(pipe);
             rx_grant.set_rssi(rssi);
-            rx_grant.commit_all();
+            let length = rx_grant.payload_len();
+            if poll_pipe.is_none() || length > 0 {
+                rx_grant.commit_all();
+            }
+            Ok(Some(length))
         } else {
             // Drop `tx_grant` and `rx_grant` so the upper stack can pass them again in the next
             // `transmit` and `prepare_for_ack`
             self.tx_grant.take();
             self.rx_grant.take();
+            Ok(None)
         }
-        Ok(ret)
+    }
+
+    // --------------- Clear channel assessment methods --------------- //
+
+    // Starts a clear channel assessment of `samples` RSSI samples on the current channel without
+    // receiving packets, the radio must be disabled. The assessment goes on in the READY and
+    // RSSIEND interrupts, which must be passed to `continue_assessment`
+    pub(crate) fn start_assessment(&mut self, samples: u8) {
+        self.assessment = Some(Assessment {
+            remaining: samples,
+            minimum: u8::MAX,
+        });
+        // No pipes enabled, so no packet will be written to memory, `transmit` enables the pipe
+        // of the packet again
+        self.radio.rxaddresses.write(|w| unsafe { w.bits(0) });
+        self.clear_ready_event();
+        self.radio.events_rssiend.reset();
+        self.radio.intenset.write(|w| w.ready().set_bit());
+
+        // "Preceding reads and writes cannot be moved past subsequent writes."
+        compiler_fence(Ordering::Release);
+
+        self.radio.tasks_rxen.write(|w| unsafe { w.bits(1) });
+    }
+
+    // Handles the events of the clear channel assessment, returns the lowest RSSI magnitude once
+    // all the samples were taken, the radio is disabled then
+    pub(crate) fn continue_assessment(&mut self) -> Option<u8> {
+        let mut assessment = self.assessment?;
+        if self.check_ready_event() {
+            // The receiver was started by the READY_START short
+            self.clear_ready_event();
+            self.radio.intenclr.write(|w| w.ready().set_bit());
+            self.radio.intenset.write(|w| w.rssiend().set_bit());
+            self.radio.tasks_rssistart.write(|w| unsafe { w.bits(1) });
+            return None;
+        }
+        if self.radio.events_rssiend.read().bits() == 0 {
+            return None;
+        }
+        self.radio.events_rssiend.reset();
+        let rssi = self.radio.rssisample.read().rssisample().bits();
+        assessment.minimum = assessment.minimum.min(rssi);
+        assessment.remaining -= 1;
+        if assessment.remaining > 0 {
+            self.assessment = Some(assessment);
+            self.radio.tasks_rssistart.write(|w| unsafe { w.bits(1) });
+            return None;
+        }
+        // Also disables the interrupts of the assessment
+        self.stop(false);
+        Some(assessment.minimum)
+    }
+
+    // --------------- Scan methods --------------- //
+
+    // Takes `samples` RSSI samples on the current channel without receiving packets, returns the
+    // minimum, the maximum and the sum of the samples. The radio must be disabled
+    pub(crate) fn sample_rssi(&mut self, samples: u16) -> (u8, u8, u32) {
+        let shorts = self.radio.shorts.read().bits();
+        let rx_addresses = self.radio.rxaddresses.read().bits();
+
+        // No pipes enabled, so no packet will be written to memory
+        self.radio.rxaddresses.write(|w| unsafe { w.bits(0) });
+        self.radio.shorts.write(|w| w.ready_start().enabled());
+        self.clear_ready_event();
+        self.radio.tasks_rxen.write(|w| unsafe { w.bits(1) });
+        while !self.check_ready_event() {}
+        self.clear_ready_event();
+
+        let (mut min, mut max, mut sum) = (u8::MAX, 0, 0);
+        for _ in 0..samples {
+            self.radio.events_rssiend.reset();
+            self.radio.tasks_rssistart.write(|w| unsafe { w.bits(1) });
+            while self.radio.events_rssiend.read().bits() == 0 {}
+            let rssi = self.radio.rssisample.read().rssisample().bits();
+            min = min.min(rssi);
+            max = max.max(rssi);
+            sum += u32::from(rssi);
+        }
+        self.radio.tasks_rssistop.write(|w| unsafe { w.bits(1) });
+        self.radio.events_rssiend.reset();
+
+        self.stop(false);
+        self.radio.shorts.write(|w| unsafe { w.bits(shorts) });
+        self.radio
+            .rxaddresses
+            .write(|w| unsafe { w.bits(rx_addresses) });
+        (min, max, sum)
     }
 
     // --------------- PRX methods --------------- //
@@ -318,16 +596,20 @@ where
     pub(crate) fn start_receiving(&mut self, mut rx_buf: PayloadW<IncomingLen>, enabled_pipes: u8) {
         // Start TX after receiving a packet as it might need an ack
         self.radio.shorts.modify(|_, w| w.disabled_txen().enabled());
+        if let Some(length) = self.static_length {
+            self.set_static_length(length);
+        }
 
         self.radio.intenset.write(|w| w.disabled().set_bit());
         self.radio
             .rxaddresses
             .write(|w| unsafe { w.bits(enabled_pipes as u32) });
 
+        let offset = self.dma_offset();
         unsafe {
             self.radio
                 .packetptr
-                .write(|w| w.bits(rx_buf.dma_pointer() as u32));
+                .write(|w| w.bits(rx_buf.dma_pointer().wrapping_add(offset) as u32));
             self.radio.events_address.write(|w| w.bits(0));
             self.clear_disabled_event();
             self.clear_ready_event();
@@ -342,25 +624,38 @@ where
         self.rx_grant = Some(rx_buf);
     }
 
+    // Stops the radio and starts listening again with the current `rx_grant`, a packet that is
+    // being received will be lost
+    pub(crate) fn restart_receiving(&mut self) {
+        self.stop(false);
+        self.resume_receiving();
+    }
+
+    // Starts listening again with the current `rx_grant` after a call to `stop(false)`
+    pub(crate) fn resume_receiving(&mut self) {
+        self.radio.shorts.modify(|_, w| w.disabled_txen().enabled());
+        self.radio.intenset.write(|w| w.disabled().set_bit());
+        // "Preceding reads and writes cannot be moved past subsequent writes."
+        compiler_fence(Ordering::Release);
+
+        // NOTE(unsafe) 1 is a valid value to write to this register
+        self.radio.tasks_rxen.write(|w| unsafe { w.bits(1) });
+    }
+
     // Check the received packet.
     #[inline]
     pub(crate) fn check_packet(
         &mut self,
+        priority_consumer: &mut FrameConsumer<'static, PriorityLen>,
         consumer: &mut FrameConsumer<'static, OutgoingLen>,
+        cancel: &TxCancel,
     ) -> Result<RxPayloadState, Error> {
         // If the user didn't provide a packet to send, we will fall back to this empty ack packet
         static FALLBACK_ACK: [u8; 2] = [0, 0];
 
-        if self.radio.crcstatus.read().crcstatus().is_crcerror() {
+        if !self.crc_ok() {
             // Bad CRC, clear events and restart RX.
-            self.stop(false);
-            self.radio.shorts.modify(|_, w| w.disabled_txen().enabled());
-            self.radio.intenset.write(|w| w.disabled().set_bit());
-            // "Preceding reads and writes cannot be moved past subsequent writes."
-            compiler_fence(Ordering::Release);
-
-            // NOTE(unsafe) 1 is a valid value to write to this register
-            self.radio.tasks_rxen.write(|w| unsafe { w.bits(1) });
+            self.restart_receiving();
             return Ok(RxPayloadState::BadCRC);
         }
         // "Subsequent reads and writes cannot be moved ahead of preceding reads."
@@ -368,10 +663,22 @@ where
         self.clear_ready_event();
 
         let pipe = self.radio.rxmatch.read().rxmatch().bits() as usize;
-        let crc = self.radio.rxcrc.read().rxcrc().bits() as u16;
-        let rx_grant = self.rx_grant.as_ref().ok_or(Error::InternalError)?;
-        let (pid, ack) = (rx_grant.pid(), !rx_grant.no_ack());
-        let repeated = (self.last_crc[pipe] == crc) && (self.last_pid[pipe] == pid);
+        let crc = self.received_crc();
+        let (static_length, legacy) = (self.static_length, self.protocol == Protocol::ShockBurst);
+        let rx_grant = self.rx_grant.as_mut().ok_or(Error::InternalError)?;
+        match static_length {
+            Some(length) if legacy => rx_grant.unpack_legacy_control_field(length),
+            Some(length) => rx_grant.unpack_static_control_field(length),
+            None => {}
+        }
+        if rx_grant.payload_len() > usize::from(self.pipe_maximum_payload[pipe]) {
+            self.restart_receiving();
+            return Ok(RxPayloadState::Rejected);
+        }
+        // Pipes without auto-ack never turn around to TX
+        let (pid, ack) = (rx_grant.pid(), !rx_grant.no_ack() && self.auto_ack[pipe]);
+        // Legacy ShockBurst packets don't have a PID, every packet is considered a new one
+        let repeated = !legacy && (self.last_crc[pipe] == crc) && (self.last_pid[pipe] == pid);
 
         if ack {
             // This is a bit risky, the radio is turning around since before the beginning of the
@@ -384,9 +691,12 @@ where
                 .txaddress
                 .write(|w| unsafe { w.txaddress().bits(pipe as u8) });
 
-            let mut dma_pointer = FALLBACK_ACK.as_ptr() as *const u8 as u32;
+            let mut dma_pointer = FALLBACK_ACK.as_ptr() as u32;
 
-            if repeated {
+            if self.static_length.is_some() {
+                // Acknowledgements don't have a payload in the static payload length mode
+                self.set_static_length(0);
+            } else if repeated {
                 if pipe == self.cached_pipe as usize {
                     if let Some(grant) = &self.tx_grant {
                         dma_pointer = grant.dma_pointer() as u32;
@@ -401,7 +711,23 @@ where
                 // "No re-ordering of reads and writes across this point is allowed."
                 compiler_fence(Ordering::SeqCst);
 
-                if let Some(payload) = consumer.read().map(PayloadR::new) {
+                // Urgent payloads go first, cancelled ones are discarded
+                let payload = loop {
+                    let payload = match priority_consumer.read() {
+                        Some(grant) => Some(TxPayload::Priority(PayloadR::new(grant))),
+                        None => consumer
+                            .read()
+                            .map(|grant| TxPayload::Normal(PayloadR::new(grant))),
+                    };
+                    match payload {
+                        Some(payload) if cancel.is_cancelled(payload.pipe(), payload.epoch()) => {
+                            cancel.count_discarded();
+                            payload.release();
+                        }
+                        payload => break payload,
+                    }
+                };
+                if let Some(payload) = payload {
                     dma_pointer = payload.dma_pointer() as u32;
                     self.tx_grant = Some(payload);
                 }
@@ -478,6 +804,10 @@ where
 
         // We don't release the `tx_grant` here, because we don't know if it was really received.
 
+        if let Some(length) = self.static_length {
+            self.set_static_length(length);
+        }
+
         // Disables the shortcut for `rxen`, we already hit that.
         // Enables the shortcut for `txen` to turn around to tx after receiving a packet
         // transmission.
@@ -494,9 +824,10 @@ where
         // `rx_grant` that we still hold if `check_packet` returned `RepeatedNoAck`. Therefore, we
         // only need to update if `rx_buf` is `Some`.
         if let Some(mut grant) = rx_buf.take() {
+            let offset = self.dma_offset();
             self.radio
                 .packetptr
-                .write(|w| unsafe { w.bits(grant.dma_pointer() as u32) });
+                .write(|w| unsafe { w.bits(grant.dma_pointer().wrapping_add(offset) as u32) });
             self.rx_grant = Some(grant);
         }
 
@@ -509,8 +840,16 @@ where
     }
 }
 
-mod sealed {
-    pub trait Sealed {}
+pub(crate) mod sealed {
+    /// Timer methods only used by the driver
+    pub trait Sealed {
+        /// Clears and starts the counter without configuring any interrupt, to measure time with
+        /// `now`.
+        fn start(&mut self);
+
+        /// Returns the current value of the counter in micro seconds, atomically.
+        fn now() -> u32;
+    }
 }
 
 /// Trait implemented for the nRF timer peripherals.
@@ -626,7 +965,23 @@ macro_rules! impl_timer {
                 }
             }
 
-            impl sealed::Sealed for $ty {}
+            // CC[2] is only used to capture the counter
+            impl sealed::Sealed for $ty {
+                #[inline]
+                fn start(&mut self) {
+                    self.tasks_clear.write(|w| unsafe { w.bits(1) });
+                    self.tasks_start.write(|w| unsafe { w.bits(1) });
+                }
+
+                #[inline]
+                fn now() -> u32 {
+                    // NOTE(unsafe) This will be used for atomic operations, only
+                    let timer = unsafe { &*Self::ptr() };
+
+                    timer.tasks_capture[2].write(|w| unsafe { w.bits(1) });
+                    timer.cc[2].read().bits()
+                }
+            }
         )+
     };
 }
@@ -636,3 +991,55 @@ impl_timer!(pac::TIMER0, pac::TIMER1, pac::TIMER2);
 
 #[cfg(feature = "51")]
 impl_timer!(pac::TIMER0);
+
+impl RandomSource for pac::RNG {
+    fn next_u32(&mut self) -> u32 {
+        // Gathers 4 bytes, the generator is left running only while they are collected
+        let mut value = 0;
+        self.events_valrdy.reset();
+        self.tasks_start.write(|w| unsafe { w.bits(1) });
+        for _ in 0..4 {
+            while self.events_valrdy.read().bits() == 0 {}
+            self.events_valrdy.reset();
+            value = (value << 8) | u32::from(self.value.read().value().bits());
+        }
+        self.tasks_stop.write(|w| unsafe { w.bits(1) });
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn padded_base_address_is_kept_by_the_radio() {
+        // The radio drops the least significant bytes of BASEn when BALEN is smaller than 4, so
+        // the padding of short base addresses must end up there
+        let base = address_conversion(u32::from_le_bytes([0x12, 0x34, 0, 0]));
+        assert_eq!(base & 0xFFFF, 0);
+        assert_eq!((base >> 24) as u8, 0x12u8.reverse_bits());
+        assert_eq!((base >> 16) as u8, 0x34u8.reverse_bits());
+    }
+
+    #[test]
+    fn crc_parameters_match_the_nrf24l01() {
+        assert_eq!(crc_parameters(Crc::OneByte), (0xFF, 0x107));
+        assert_eq!(crc_parameters(Crc::TwoBytes), (0xFFFF, 0x1_1021));
+        assert_eq!(crc_parameters(Crc::Disabled), (0, 0));
+    }
+
+    #[test]
+    fn prefixes_keep_their_byte_order() {
+        let prefixes = bytewise_bit_swap(u32::from_le_bytes([0xE7, 0xC2, 0xC3, 0xC4]));
+        assert_eq!(
+            prefixes.to_le_bytes(),
+            [
+                0xE7u8.reverse_bits(),
+                0xC2u8.reverse_bits(),
+                0xC3u8.reverse_bits(),
+                0xC4u8.reverse_bits()
+            ]
+        );
+    }
+}
@@ -0,0 +1,177 @@
+//! Acknowledgement payload polling
+//!
+//! The PRX can only send data to the PTX in the payload of its acknowledgements, so data only
+//! flows from the PRX while the PTX is sending packets. With polling enabled through
+//! [EsbIrq::enable_polling](../irq/struct.EsbIrq.html#method.enable_polling), the PTX sends an
+//! empty packet requesting an acknowledgement when its outgoing queue stays empty for the
+//! polling interval.
+//!
+//! The interval doubles, up to `maximum_interval`, each time a poll is answered by an empty
+//! acknowledgement or not answered at all, and goes back to `interval` when an acknowledgement
+//! carries a payload. Polls are never retransmitted, packets queued by the application are
+//! always sent first, and empty acknowledgements of polls are not passed to the application.
+//!
+//! Polls are counted as sent packets in the [link statistics](../stats/index.html).
+
+use crate::Error;
+
+/// Polling settings
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Polling {
+    pipe: u8,
+    interval: u16,
+    maximum_interval: u16,
+}
+
+impl Polling {
+    /// Creates new polling settings
+    ///
+    /// * `pipe` - Pipe used to send the polls.
+    /// * `interval` - Microseconds the outgoing queue must stay empty before a poll is sent.
+    /// * `maximum_interval` - Longest interval, in microseconds, reached by doubling `interval`
+    ///   while the PRX has nothing to send.
+    ///
+    /// # Errors
+    ///
+    /// An error will be returned if `pipe` is greater than 7, if `interval` is zero or if
+    /// `maximum_interval` is smaller than `interval`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use esb::polling::Polling;
+    ///
+    /// let polling = Polling::new(0, 2_000, 32_000).unwrap();
+    /// assert_eq!(polling.interval(), 2_000);
+    ///
+    /// assert!(Polling::new(8, 2_000, 32_000).is_err());
+    /// assert!(Polling::new(0, 2_000, 1_000).is_err());
+    /// ```
+    pub fn new(pipe: u8, interval: u16, maximum_interval: u16) -> Result<Self, Error> {
+        if pipe > 7 || interval == 0 || maximum_interval < interval {
+            return Err(Error::InvalidParameters);
+        }
+        Ok(Self {
+            pipe,
+            interval,
+            maximum_interval,
+        })
+    }
+
+    /// Pipe used to send the polls
+    pub fn pipe(&self) -> u8 {
+        self.pipe
+    }
+
+    /// Microseconds the outgoing queue must stay empty before a poll is sent
+    pub fn interval(&self) -> u16 {
+        self.interval
+    }
+
+    /// Longest interval between polls, in microseconds
+    pub fn maximum_interval(&self) -> u16 {
+        self.maximum_interval
+    }
+}
+
+/// Polling state kept by [EsbIrq](../irq/struct.EsbIrq.html)
+pub(crate) struct Poller {
+    pub(crate) polling: Polling,
+    interval: u16,
+    pid: u8,
+    pub(crate) in_flight: bool,
+}
+
+impl Poller {
+    pub(crate) fn new(polling: Polling) -> Self {
+        Self {
+            polling,
+            interval: polling.interval,
+            pid: 0,
+            in_flight: false,
+        }
+    }
+
+    /// Current interval between polls
+    pub(crate) fn interval(&self) -> u16 {
+        self.interval
+    }
+
+    /// Moves to the PID of the next poll, it always differs from the PID of the last packet sent
+    /// on the pipe
+    pub(crate) fn next_pid(&mut self) {
+        self.pid = (self.pid + 1) & 0b11;
+    }
+
+    /// PID of the poll being sent
+    pub(crate) fn pid(&self) -> u8 {
+        self.pid
+    }
+
+    /// Takes note of a packet of the application sent on `pipe`
+    pub(crate) fn packet_sent(&mut self, pipe: u8, pid: u8) {
+        if pipe == self.polling.pipe {
+            self.pid = pid;
+        }
+    }
+
+    /// Ends the poll in flight, `data` tells whether the PRX answered with a payload
+    pub(crate) fn poll_done(&mut self, data: bool) {
+        self.in_flight = false;
+        self.interval = if data {
+            self.polling.interval
+        } else {
+            self.interval
+                .saturating_mul(2)
+                .min(self.polling.maximum_interval)
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interval_backs_off_while_idle() {
+        let mut poller = Poller::new(Polling::new(0, 1_000, 5_000).unwrap());
+        assert_eq!(poller.interval(), 1_000);
+        poller.in_flight = true;
+        poller.poll_done(false);
+        assert!(!poller.in_flight);
+        assert_eq!(poller.interval(), 2_000);
+        poller.poll_done(false);
+        assert_eq!(poller.interval(), 4_000);
+        poller.poll_done(false);
+        assert_eq!(poller.interval(), 5_000);
+        poller.poll_done(false);
+        assert_eq!(poller.interval(), 5_000);
+        // Data from the PRX brings back the shortest interval
+        poller.poll_done(true);
+        assert_eq!(poller.interval(), 1_000);
+    }
+
+    #[test]
+    fn interval_does_not_overflow() {
+        let mut poller = Poller::new(Polling::new(0, 40_000, u16::MAX).unwrap());
+        poller.poll_done(false);
+        assert_eq!(poller.interval(), u16::MAX);
+        poller.poll_done(false);
+        assert_eq!(poller.interval(), u16::MAX);
+    }
+
+    #[test]
+    fn poll_pid_follows_the_pipe() {
+        let mut poller = Poller::new(Polling::new(2, 1_000, 5_000).unwrap());
+        poller.next_pid();
+        assert_eq!(poller.pid(), 1);
+        // Packets of other pipes don't change the pid of the polls
+        poller.packet_sent(1, 3);
+        poller.next_pid();
+        assert_eq!(poller.pid(), 2);
+        // The pid of a poll always differs from the one of the last packet of its pipe
+        poller.packet_sent(2, 3);
+        poller.next_pid();
+        assert_eq!(poller.pid(), 0);
+    }
+}
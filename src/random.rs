@@ -0,0 +1,73 @@
+//! Random number sources
+//!
+//! The random retransmit backoff, see [Backoff](../struct.Backoff.html), draws its values from a
+//! fast software generator kept by [EsbIrq](../irq/struct.EsbIrq.html), so the radio interrupt
+//! never waits for a hardware generator. That generator is seeded from a
+//! [RandomSource](trait.RandomSource.html) with
+//! [EsbIrq::seed_backoff](../irq/struct.EsbIrq.html#method.seed_backoff), e.g. the `RNG`
+//! peripheral, or a [XorShift32](struct.XorShift32.html) with a fixed seed for reproducible
+//! tests.
+
+/// A source of random numbers
+pub trait RandomSource {
+    /// Returns the next random value
+    fn next_u32(&mut self) -> u32;
+}
+
+/// Seed used when the generator is created with zero, the only seed xorshift can't use
+const XORSHIFT_DEFAULT_SEED: u32 = 0x2545_F491;
+
+/// Marsaglia's xorshift32 pseudo random generator, it is not suitable for cryptographic use
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct XorShift32 {
+    state: u32,
+}
+
+impl XorShift32 {
+    /// Creates a new generator, a zero `seed` is replaced by a fixed non-zero seed
+    pub const fn new(seed: u32) -> Self {
+        Self {
+            state: if seed == 0 {
+                XORSHIFT_DEFAULT_SEED
+            } else {
+                seed
+            },
+        }
+    }
+}
+
+impl Default for XorShift32 {
+    fn default() -> Self {
+        Self::new(XORSHIFT_DEFAULT_SEED)
+    }
+}
+
+impl RandomSource for XorShift32 {
+    fn next_u32(&mut self) -> u32 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.state = x;
+        x
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zero_seed_is_replaced() {
+        assert_eq!(XorShift32::new(0), XorShift32::default());
+        assert_ne!(XorShift32::new(0).next_u32(), 0);
+    }
+
+    #[test]
+    fn xorshift_sequence() {
+        let mut random = XorShift32::new(1);
+        assert_eq!(random.next_u32(), 270_369);
+        assert_eq!(random.next_u32(), 67_634_689);
+        assert_eq!(random.next_u32(), 2_647_435_461);
+    }
+}
@@ -0,0 +1,356 @@
+//! Transmit reports
+//!
+//! In PTX mode, packets with a tag set through
+//! [`EsbHeaderBuilder::tag`](../payload/struct.EsbHeaderBuilder.html#method.tag) generate a
+//! [`TxReport`](struct.TxReport.html) once the driver is done with them, which can be read with
+//! [`EsbApp::read_tx_report`](../app/struct.EsbApp.html#method.read_tx_report).
+//!
+//! The result of the last link probe, see
+//! [`EsbApp::probe`](../app/struct.EsbApp.html#method.probe), is kept separately and read with
+//! [`EsbApp::probe_result`](../app/struct.EsbApp.html#method.probe_result).
+//!
+//! The reports are kept in a small ring of [`TX_REPORTS_CAPACITY`](constant.TX_REPORTS_CAPACITY.html)
+//! entries, reports generated while the ring is full are lost.
+
+use core::{
+    convert::TryFrom,
+    sync::atomic::{AtomicU32, AtomicU8, Ordering},
+};
+
+/// Number of reports that can be waiting to be read
+pub const TX_REPORTS_CAPACITY: usize = 16;
+
+/// Outcome of a tagged packet
+///
+/// New outcomes may be added in future versions, so matching on it requires a wildcard arm.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TxOutcome {
+    /// The packet was acknowledged by the PRX
+    Acked,
+    /// The packet was sent without requesting an acknowledgement
+    Sent,
+    /// The packet was dropped after reaching the maximum number of attempts
+    Dropped,
+    /// The packet was dropped because its deadline passed
+    Expired,
+    /// The packet was discarded by a cancellation, see the [cancel](../cancel/index.html) module
+    Cancelled,
+    /// The packet was dropped because the channel was found busy too many times in a row, see
+    /// [Cca](../struct.Cca.html)
+    ChannelBusy,
+}
+
+/// Report of a tagged packet
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TxReport {
+    tag: u8,
+    outcome: TxOutcome,
+    attempts: u8,
+}
+
+impl TxReport {
+    pub(crate) fn new(tag: u8, outcome: TxOutcome, attempts: u8) -> Self {
+        Self {
+            tag,
+            outcome,
+            attempts,
+        }
+    }
+
+    /// Tag set in the header of the packet
+    pub fn tag(&self) -> u8 {
+        self.tag
+    }
+
+    /// What happened to the packet
+    pub fn outcome(&self) -> TxOutcome {
+        self.outcome
+    }
+
+    /// Number of times the packet was transmitted
+    pub fn attempts(&self) -> u8 {
+        self.attempts
+    }
+
+    fn to_bits(self) -> u32 {
+        u32::from(self.tag)
+            | (u32::from(outcome_to_bits(self.outcome)) << 8)
+            | (u32::from(self.attempts) << 16)
+    }
+
+    fn from_bits(bits: u32) -> Self {
+        Self {
+            tag: bits as u8,
+            outcome: outcome_from_bits((bits >> 8) as u8),
+            attempts: (bits >> 16) as u8,
+        }
+    }
+}
+
+/// Result of a link probe
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ProbeResult {
+    outcome: TxOutcome,
+    attempts: u8,
+    round_trip_time: u16,
+}
+
+impl ProbeResult {
+    pub(crate) fn new(outcome: TxOutcome, attempts: u8, round_trip_time: u32) -> Self {
+        Self {
+            outcome,
+            attempts,
+            round_trip_time: u16::try_from(round_trip_time).unwrap_or(u16::MAX),
+        }
+    }
+
+    /// What happened to the probe, the PRX is reachable if it is `TxOutcome::Acked`
+    pub fn outcome(&self) -> TxOutcome {
+        self.outcome
+    }
+
+    /// Number of times the probe was transmitted
+    pub fn attempts(&self) -> u8 {
+        self.attempts
+    }
+
+    /// Time in microseconds from the start of the successful transmission, including the ramp-up,
+    /// to the handling of the end of the acknowledgement by the radio interrupt, `None` if the
+    /// probe was not acknowledged
+    ///
+    /// Only the last attempt is measured, the time spent in the previous attempts and in the
+    /// retransmission delays is not included.
+    pub fn round_trip_time(&self) -> Option<u16> {
+        if self.outcome == TxOutcome::Acked {
+            Some(self.round_trip_time)
+        } else {
+            None
+        }
+    }
+
+    fn to_bits(self) -> u32 {
+        u32::from(self.round_trip_time)
+            | (u32::from(self.attempts) << 16)
+            | (u32::from(outcome_to_bits(self.outcome)) << 24)
+    }
+
+    fn from_bits(bits: u32) -> Self {
+        Self {
+            outcome: outcome_from_bits((bits >> 24) as u8),
+            attempts: (bits >> 16) as u8,
+            round_trip_time: bits as u16,
+        }
+    }
+}
+
+fn outcome_to_bits(outcome: TxOutcome) -> u8 {
+    match outcome {
+        TxOutcome::Acked => 0,
+        TxOutcome::Sent => 1,
+        TxOutcome::Dropped => 2,
+        TxOutcome::Expired => 3,
+        TxOutcome::Cancelled => 4,
+        TxOutcome::ChannelBusy => 5,
+    }
+}
+
+fn outcome_from_bits(bits: u8) -> TxOutcome {
+    match bits {
+        0 => TxOutcome::Acked,
+        1 => TxOutcome::Sent,
+        2 => TxOutcome::Dropped,
+        3 => TxOutcome::Expired,
+        4 => TxOutcome::Cancelled,
+        5 => TxOutcome::ChannelBusy,
+        // The bits are only ever written by `outcome_to_bits`
+        _ => unreachable!(),
+    }
+}
+
+/// Storage of the transmit reports, shared by [`EsbIrq`](../irq/struct.EsbIrq.html) and
+/// [`EsbApp`](../app/struct.EsbApp.html)
+///
+/// It is a single producer, single consumer ring that only needs atomic loads and stores, the
+/// interrupt is the only writer of `head` and the application is the only writer of `tail`.
+/// The probe result is only written by the interrupt, its sequence number is written last.
+pub(crate) struct TxReports {
+    slots: [AtomicU32; TX_REPORTS_CAPACITY],
+    head: AtomicU8,
+    tail: AtomicU8,
+    probe: AtomicU32,
+    probe_seq: AtomicU8,
+}
+
+impl TxReports {
+    /// Creates an empty report ring, used for the `static` creation of
+    /// [`EsbBuffer`](../buffer/struct.EsbBuffer.html)
+    #[allow(clippy::declare_interior_mutable_const)]
+    pub(crate) const fn new() -> Self {
+        const EMPTY: AtomicU32 = AtomicU32::new(0);
+        Self {
+            slots: [EMPTY; TX_REPORTS_CAPACITY],
+            head: AtomicU8::new(0),
+            tail: AtomicU8::new(0),
+            probe: AtomicU32::new(0),
+            probe_seq: AtomicU8::new(0),
+        }
+    }
+
+    /// Empties the ring, must only be called before the ring is shared
+    pub(crate) fn clear(&self) {
+        self.head.store(0, Ordering::Relaxed);
+        self.probe_seq.store(0, Ordering::Relaxed);
+        self.tail.store(0, Ordering::Release);
+    }
+
+    /// Stores the result of the probe with the sequence number `seq`
+    pub(crate) fn set_probe(&self, seq: u8, result: ProbeResult) {
+        self.probe.store(result.to_bits(), Ordering::Relaxed);
+        self.probe_seq.store(seq, Ordering::Release);
+    }
+
+    /// Result of the probe with the sequence number `seq`, if it is done
+    pub(crate) fn probe(&self, seq: u8) -> Option<ProbeResult> {
+        if self.probe_seq.load(Ordering::Acquire) != seq {
+            return None;
+        }
+        // Results of later probes are only written once the application sent them
+        Some(ProbeResult::from_bits(self.probe.load(Ordering::Relaxed)))
+    }
+
+    /// Adds a report, the report is lost if the ring is full
+    pub(crate) fn push(&self, report: TxReport) {
+        let head = self.head.load(Ordering::Relaxed);
+        let tail = self.tail.load(Ordering::Acquire);
+        if usize::from(head.wrapping_sub(tail)) == TX_REPORTS_CAPACITY {
+            return;
+        }
+        self.slots[usize::from(head) % TX_REPORTS_CAPACITY]
+            .store(report.to_bits(), Ordering::Relaxed);
+        self.head.store(head.wrapping_add(1), Ordering::Release);
+    }
+
+    /// Takes the oldest report
+    pub(crate) fn pop(&self) -> Option<TxReport> {
+        let tail = self.tail.load(Ordering::Relaxed);
+        let head = self.head.load(Ordering::Acquire);
+        if head == tail {
+            return None;
+        }
+        let bits = self.slots[usize::from(tail) % TX_REPORTS_CAPACITY].load(Ordering::Relaxed);
+        self.tail.store(tail.wrapping_add(1), Ordering::Release);
+        Some(TxReport::from_bits(bits))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OUTCOMES: [TxOutcome; 6] = [
+        TxOutcome::Acked,
+        TxOutcome::Sent,
+        TxOutcome::Dropped,
+        TxOutcome::Expired,
+        TxOutcome::Cancelled,
+        TxOutcome::ChannelBusy,
+    ];
+
+    #[test]
+    fn report_bits_round_trip() {
+        for &outcome in OUTCOMES.iter() {
+            let report = TxReport::new(0xA5, outcome, 255);
+            assert_eq!(TxReport::from_bits(report.to_bits()), report);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn unknown_outcome_bits_are_unreachable() {
+        outcome_from_bits(OUTCOMES.len() as u8);
+    }
+
+    #[test]
+    fn reports_are_read_in_order() {
+        let reports = TxReports::new();
+        assert_eq!(reports.pop(), None);
+        reports.push(TxReport::new(1, TxOutcome::Acked, 1));
+        reports.push(TxReport::new(2, TxOutcome::Dropped, 3));
+        assert_eq!(reports.pop(), Some(TxReport::new(1, TxOutcome::Acked, 1)));
+        assert_eq!(reports.pop(), Some(TxReport::new(2, TxOutcome::Dropped, 3)));
+        assert_eq!(reports.pop(), None);
+    }
+
+    #[test]
+    fn reports_are_lost_when_full() {
+        let reports = TxReports::new();
+        for tag in 0..=TX_REPORTS_CAPACITY as u8 {
+            reports.push(TxReport::new(tag, TxOutcome::Sent, 1));
+        }
+        for tag in 0..TX_REPORTS_CAPACITY as u8 {
+            assert_eq!(reports.pop().map(|report| report.tag()), Some(tag));
+        }
+        assert_eq!(reports.pop(), None);
+    }
+
+    #[test]
+    fn ring_wraps_around() {
+        let reports = TxReports::new();
+        // Goes past the wrap of the `u8` indices a few times, with the ring partially filled
+        for round in 0..200u32 {
+            for i in 0..3 {
+                reports.push(TxReport::new((round * 3 + i) as u8, TxOutcome::Acked, 1));
+            }
+            for i in 0..3 {
+                let report = reports.pop().unwrap();
+                assert_eq!(report.tag(), (round * 3 + i) as u8);
+            }
+        }
+        assert_eq!(reports.pop(), None);
+
+        // The ring still holds its full capacity after the indices wrapped
+        for tag in 0..TX_REPORTS_CAPACITY as u8 {
+            reports.push(TxReport::new(tag, TxOutcome::Sent, 1));
+        }
+        reports.push(TxReport::new(0xFF, TxOutcome::Sent, 1));
+        for tag in 0..TX_REPORTS_CAPACITY as u8 {
+            assert_eq!(reports.pop().map(|report| report.tag()), Some(tag));
+        }
+        assert_eq!(reports.pop(), None);
+    }
+
+    #[test]
+    fn clear_empties_the_ring() {
+        let reports = TxReports::new();
+        reports.push(TxReport::new(1, TxOutcome::Acked, 1));
+        reports.clear();
+        assert_eq!(reports.pop(), None);
+    }
+
+    #[test]
+    fn probe_result_bits_round_trip() {
+        for &outcome in OUTCOMES.iter() {
+            let result = ProbeResult::new(outcome, 15, 1234);
+            assert_eq!(ProbeResult::from_bits(result.to_bits()), result);
+        }
+        // Round-trip times that don't fit are saturated
+        let result = ProbeResult::new(TxOutcome::Acked, 1, 70_000);
+        assert_eq!(result.round_trip_time(), Some(u16::MAX));
+        let result = ProbeResult::new(TxOutcome::Dropped, 3, 0);
+        assert_eq!(result.round_trip_time(), None);
+    }
+
+    #[test]
+    fn probe_result_matches_the_sequence_number() {
+        let reports = TxReports::new();
+        assert_eq!(reports.probe(1), None);
+        let result = ProbeResult::new(TxOutcome::Acked, 2, 500);
+        reports.set_probe(1, result);
+        assert_eq!(reports.probe(1), Some(result));
+        // A later probe is still in progress
+        assert_eq!(reports.probe(2), None);
+        reports.clear();
+        assert_eq!(reports.probe(1), None);
+    }
+}
@@ -0,0 +1,251 @@
+//! Link statistics
+//!
+//! [EsbIrq](../irq/struct.EsbIrq.html) counts the events of the link in a block of
+//! [EsbBuffer](../buffer/struct.EsbBuffer.html), similar to the `OBSERVE_TX` register of the
+//! nRF24L01+, and
+//! [EsbApp::stats](../app/struct.EsbApp.html#method.stats) reads them without locking.
+//!
+//! The counters are 32 bits wide and wrap around.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+/// Snapshot of the link statistics
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct LinkStats {
+    /// Packets transmitted by the PTX for the first time
+    pub sent: u32,
+    /// Packets acknowledged by the PRX
+    pub acked: u32,
+    /// Retransmissions of packets by the PTX
+    pub retransmissions: u32,
+    /// Packets dropped by the PTX after reaching the maximum number of attempts
+    pub dropped: u32,
+    /// Packets and acknowledgements received with a bad CRC
+    pub bad_crc: u32,
+    /// Retransmitted packets received by the PRX and not passed to the application again
+    pub duplicates: u32,
+    /// Packets or acknowledgements lost because the incoming queue was full
+    pub incoming_queue_full: u32,
+    /// Clear channel assessments of the PTX that found the channel busy
+    pub channel_busy: u32,
+    /// New packets received by the PRX, per pipe
+    pub received: [u32; 8],
+}
+
+impl LinkStats {
+    /// Counters of `self` since `baseline`
+    pub(crate) fn since(&self, baseline: &LinkStats) -> LinkStats {
+        let mut received = [0; 8];
+        for (count, (now, then)) in received
+            .iter_mut()
+            .zip(self.received.iter().zip(baseline.received.iter()))
+        {
+            *count = now.wrapping_sub(*then);
+        }
+        LinkStats {
+            sent: self.sent.wrapping_sub(baseline.sent),
+            acked: self.acked.wrapping_sub(baseline.acked),
+            retransmissions: self.retransmissions.wrapping_sub(baseline.retransmissions),
+            dropped: self.dropped.wrapping_sub(baseline.dropped),
+            bad_crc: self.bad_crc.wrapping_sub(baseline.bad_crc),
+            duplicates: self.duplicates.wrapping_sub(baseline.duplicates),
+            incoming_queue_full: self
+                .incoming_queue_full
+                .wrapping_sub(baseline.incoming_queue_full),
+            channel_busy: self.channel_busy.wrapping_sub(baseline.channel_busy),
+            received,
+        }
+    }
+}
+
+/// Counters shared by [EsbIrq](../irq/struct.EsbIrq.html) and [EsbApp](../app/struct.EsbApp.html)
+///
+/// Only atomic loads and stores are needed, the interrupt is the only writer. The counters are
+/// never cleared after the buffer is split, resetting them in the application only moves its
+/// baseline.
+pub(crate) struct EsbStats {
+    sent: AtomicU32,
+    acked: AtomicU32,
+    retransmissions: AtomicU32,
+    dropped: AtomicU32,
+    bad_crc: AtomicU32,
+    duplicates: AtomicU32,
+    incoming_queue_full: AtomicU32,
+    channel_busy: AtomicU32,
+    received: [AtomicU32; 8],
+}
+
+impl EsbStats {
+    /// Creates zeroed counters, used for the `static` creation of
+    /// [`EsbBuffer`](../buffer/struct.EsbBuffer.html)
+    #[allow(clippy::declare_interior_mutable_const)]
+    pub(crate) const fn new() -> Self {
+        const ZERO: AtomicU32 = AtomicU32::new(0);
+        Self {
+            sent: ZERO,
+            acked: ZERO,
+            retransmissions: ZERO,
+            dropped: ZERO,
+            bad_crc: ZERO,
+            duplicates: ZERO,
+            incoming_queue_full: ZERO,
+            channel_busy: ZERO,
+            received: [ZERO; 8],
+        }
+    }
+
+    /// Zeroes the counters, must only be called before they are shared
+    pub(crate) fn clear(&self) {
+        for counter in [
+            &self.sent,
+            &self.acked,
+            &self.retransmissions,
+            &self.dropped,
+            &self.bad_crc,
+            &self.duplicates,
+            &self.incoming_queue_full,
+            &self.channel_busy,
+        ]
+        .iter()
+        .copied()
+        .chain(self.received.iter())
+        {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+
+    /// Reads all the counters, they are not read at the same instant
+    pub(crate) fn snapshot(&self) -> LinkStats {
+        let mut received = [0; 8];
+        for (count, counter) in received.iter_mut().zip(self.received.iter()) {
+            *count = counter.load(Ordering::Relaxed);
+        }
+        LinkStats {
+            sent: self.sent.load(Ordering::Relaxed),
+            acked: self.acked.load(Ordering::Relaxed),
+            retransmissions: self.retransmissions.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            bad_crc: self.bad_crc.load(Ordering::Relaxed),
+            duplicates: self.duplicates.load(Ordering::Relaxed),
+            incoming_queue_full: self.incoming_queue_full.load(Ordering::Relaxed),
+            channel_busy: self.channel_busy.load(Ordering::Relaxed),
+            received,
+        }
+    }
+
+    pub(crate) fn count_sent(&self) {
+        increment(&self.sent);
+    }
+
+    pub(crate) fn count_acked(&self) {
+        increment(&self.acked);
+    }
+
+    pub(crate) fn count_retransmission(&self) {
+        increment(&self.retransmissions);
+    }
+
+    pub(crate) fn count_dropped(&self) {
+        increment(&self.dropped);
+    }
+
+    pub(crate) fn count_bad_crc(&self) {
+        increment(&self.bad_crc);
+    }
+
+    pub(crate) fn count_duplicate(&self) {
+        increment(&self.duplicates);
+    }
+
+    pub(crate) fn count_incoming_queue_full(&self) {
+        increment(&self.incoming_queue_full);
+    }
+
+    pub(crate) fn count_channel_busy(&self) {
+        increment(&self.channel_busy);
+    }
+
+    pub(crate) fn count_received(&self, pipe: u8) {
+        increment(&self.received[usize::from(pipe)]);
+    }
+}
+
+// Single writer increment, thumbv6 doesn't have atomic read-modify-write operations
+fn increment(counter: &AtomicU32) {
+    counter.store(
+        counter.load(Ordering::Relaxed).wrapping_add(1),
+        Ordering::Relaxed,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn since_subtracts_every_counter() {
+        let stats = EsbStats::new();
+        let baseline = stats.snapshot();
+        stats.count_sent();
+        stats.count_sent();
+        stats.count_acked();
+        stats.count_retransmission();
+        stats.count_dropped();
+        stats.count_bad_crc();
+        stats.count_duplicate();
+        stats.count_incoming_queue_full();
+        stats.count_channel_busy();
+        stats.count_received(7);
+        let middle = stats.snapshot();
+        stats.count_sent();
+
+        let mut received = [0; 8];
+        received[7] = 1;
+        let expected = LinkStats {
+            sent: 2,
+            acked: 1,
+            retransmissions: 1,
+            dropped: 1,
+            bad_crc: 1,
+            duplicates: 1,
+            incoming_queue_full: 1,
+            channel_busy: 1,
+            received,
+        };
+        assert_eq!(middle.since(&baseline), expected);
+        assert_eq!(
+            stats.snapshot().since(&middle),
+            LinkStats {
+                sent: 1,
+                ..LinkStats::default()
+            }
+        );
+    }
+
+    #[test]
+    fn since_handles_wrapped_counters() {
+        let baseline = LinkStats {
+            sent: u32::MAX - 1,
+            received: [u32::MAX; 8],
+            ..LinkStats::default()
+        };
+        let now = LinkStats {
+            sent: 3,
+            received: [1; 8],
+            ..LinkStats::default()
+        };
+        let delta = now.since(&baseline);
+        assert_eq!(delta.sent, 5);
+        assert_eq!(delta.received, [2; 8]);
+        assert_eq!(delta.acked, 0);
+    }
+
+    #[test]
+    fn clear_zeroes_the_counters() {
+        let stats = EsbStats::new();
+        stats.count_sent();
+        stats.count_received(3);
+        stats.clear();
+        assert_eq!(stats.snapshot(), LinkStats::default());
+    }
+}